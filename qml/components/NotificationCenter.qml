@@ -0,0 +1,233 @@
+import QtQuick 2.15
+import QtQuick.Controls 2.15
+import QtQuick.Layouts 1.15
+import "."
+import "../styles"
+
+Drawer {
+    id: notificationCenter
+    edge: Qt.RightEdge
+    width: 360
+    height: parent ? parent.height : 0
+
+    property var controller
+    property string currentTheme: "system"
+
+    background: Rectangle {
+        color: currentTheme === "dark" ? "#232323" : "#ffffff"
+        border.color: currentTheme === "dark" ? "#444" : "#bbb"
+    }
+
+    ColumnLayout {
+        anchors.fill: parent
+        anchors.margins: 10
+        spacing: 8
+
+        TabBar {
+            id: centerTabs
+            Layout.fillWidth: true
+
+            TabButton {
+                text: "Notifications"
+            }
+            TabButton {
+                text: controller && controller.call_log.missed_count > 0
+                      ? "Calls (" + controller.call_log.missed_count + ")" : "Calls"
+            }
+        }
+
+        StackLayout {
+            Layout.fillWidth: true
+            Layout.fillHeight: true
+            currentIndex: centerTabs.currentIndex
+
+            ColumnLayout {
+                spacing: 8
+
+                RowLayout {
+                    Layout.fillWidth: true
+
+                    Label {
+                        text: "Notifications"
+                        font.bold: true
+                        font.pointSize: 12
+                        Layout.fillWidth: true
+                        color: currentTheme === "dark" ? "#fff" : "#222"
+                    }
+                    Button {
+                        text: "Mark all read"
+                        enabled: controller && controller.notification_history.unread_count > 0
+                        onClicked: controller.notification_history.mark_all_read()
+                    }
+                    Button {
+                        text: "Clear"
+                        onClicked: controller.notification_history.clear()
+                    }
+                }
+
+                ListView {
+                    id: historyList
+                    Layout.fillWidth: true
+                    Layout.fillHeight: true
+                    clip: true
+                    spacing: 4
+                    model: controller ? controller.notification_history : null
+
+                    delegate: Rectangle {
+                        width: historyList.width
+                        height: entryLayout.implicitHeight + 12
+                        radius: 4
+                        color: model.read ? "transparent"
+                             : (currentTheme === "dark" ? "#2f3b38" : "#e8f5e9")
+                        border.width: 1
+                        border.color: currentTheme === "dark" ? "#444" : "#ddd"
+
+                        ColumnLayout {
+                            id: entryLayout
+                            anchors.fill: parent
+                            anchors.margins: 6
+                            spacing: 2
+
+                            RowLayout {
+                                Layout.fillWidth: true
+                                Label {
+                                    text: (model.kind === "call" ? "📞 " : "💬 ") + (model.sender || "Unknown")
+                                    font.bold: !model.read
+                                    elide: Text.ElideRight
+                                    Layout.fillWidth: true
+                                    color: currentTheme === "dark" ? "#fff" : "#222"
+                                }
+                                Label {
+                                    text: model.time
+                                    font.pointSize: 8
+                                    color: "#888"
+                                }
+                            }
+                            Label {
+                                text: model.account
+                                font.pointSize: 8
+                                color: "#888"
+                            }
+                            Label {
+                                text: model.preview
+                                visible: model.kind === "message"
+                                wrapMode: Text.Wrap
+                                maximumLineCount: 2
+                                elide: Text.ElideRight
+                                Layout.fillWidth: true
+                                color: currentTheme === "dark" ? "#ccc" : "#444"
+                            }
+                            Label {
+                                text: model.reason
+                                visible: model.reason.length > 0
+                                font.pointSize: 8
+                                font.italic: true
+                                color: "#e08a00"
+                            }
+                        }
+
+                        MouseArea {
+                            anchors.fill: parent
+                            onClicked: {
+                                controller.open_history_entry(index);
+                                notificationCenter.close();
+                            }
+                        }
+                    }
+
+                    Label {
+                        anchors.centerIn: parent
+                        visible: historyList.count === 0
+                        text: "No notifications yet"
+                        color: "#888"
+                    }
+                }
+            }
+
+            ColumnLayout {
+                spacing: 8
+
+                RowLayout {
+                    Layout.fillWidth: true
+
+                    Label {
+                        text: "Calls"
+                        font.bold: true
+                        font.pointSize: 12
+                        Layout.fillWidth: true
+                        color: currentTheme === "dark" ? "#fff" : "#222"
+                    }
+                    Button {
+                        text: "Mark all seen"
+                        enabled: controller && controller.call_log.missed_count > 0
+                        onClicked: controller.call_log.mark_all_seen()
+                    }
+                    Button {
+                        text: "Clear"
+                        onClicked: controller.call_log.clear()
+                    }
+                }
+
+                ListView {
+                    id: callList
+                    Layout.fillWidth: true
+                    Layout.fillHeight: true
+                    clip: true
+                    spacing: 4
+                    model: controller ? controller.call_log : null
+
+                    delegate: Rectangle {
+                        width: callList.width
+                        height: callLayout.implicitHeight + 12
+                        radius: 4
+                        color: model.seen ? "transparent"
+                             : (currentTheme === "dark" ? "#3b2f2f" : "#fdecea")
+                        border.width: 1
+                        border.color: currentTheme === "dark" ? "#444" : "#ddd"
+
+                        RowLayout {
+                            id: callLayout
+                            anchors.fill: parent
+                            anchors.margins: 6
+
+                            ColumnLayout {
+                                Layout.fillWidth: true
+                                spacing: 2
+
+                                Label {
+                                    text: (model.kind === "video" ? "🎥 " : "📞 ") + (model.caller || "Unknown")
+                                    font.bold: !model.seen
+                                    elide: Text.ElideRight
+                                    Layout.fillWidth: true
+                                    color: model.outcome === "missed" ? "#e53935"
+                                         : (currentTheme === "dark" ? "#fff" : "#222")
+                                }
+                                Label {
+                                    text: model.account + " · " + model.outcome + " " + model.kind + " call · " + model.time
+                                    font.pointSize: 8
+                                    elide: Text.ElideRight
+                                    Layout.fillWidth: true
+                                    color: "#888"
+                                }
+                            }
+                            Button {
+                                text: "Call back"
+                                onClicked: {
+                                    controller.call_back(index);
+                                    notificationCenter.close();
+                                }
+                            }
+                        }
+                    }
+
+                    Label {
+                        anchors.centerIn: parent
+                        visible: callList.count === 0
+                        text: "No calls yet"
+                        color: "#888"
+                    }
+                }
+            }
+        }
+    }
+}
@@ -15,9 +15,26 @@ Dialog {
 
     property var controller
 
+    // Why the policy locks `key`, empty when it is not locked.
+    function lockedReason(key) {
+        return controller ? controller.locked_reason(key) : "";
+    }
+
+    component LockNote: Label {
+        property string key
+        readonly property string reason: settingsDialog.lockedReason(key)
+        visible: reason !== ""
+        text: "🔒 " + reason
+        font.italic: true
+        opacity: 0.7
+        Layout.fillWidth: true
+        wrapMode: Text.Wrap
+    }
+
     onAccepted: {
-        var selectedTheme = themeCombo.currentText.toLowerCase();
+        var selectedTheme = themeCombo.currentText;
         console.log("Applying theme:", selectedTheme);
+        controller.set_theme_strategy("whatsapp", whatsappStrategyCombo.currentValue);
         controller.set_theme(selectedTheme);
         controller.save_settings();
         // controller.set_theme(themeCombo.currentText.toLowerCase());
@@ -33,6 +50,45 @@ Dialog {
             width: parent.width
             spacing: 20
 
+            GroupBox {
+                title: "Profile"
+                Layout.fillWidth: true
+
+                ColumnLayout {
+                    anchors.fill: parent
+
+                    RowLayout {
+                        Label {
+                            text: "Active profile:"
+                        }
+                        ComboBox {
+                            id: profileCombo
+                            Layout.fillWidth: true
+                            model: controller.list_profiles()
+                            Component.onCompleted: currentIndex = Math.max(0, find(controller.profile))
+                            onActivated: controller.switch_profile(currentText)
+                        }
+                    }
+                    RowLayout {
+                        TextField {
+                            id: newProfileField
+                            Layout.fillWidth: true
+                            placeholderText: "New profile, e.g. evening"
+                        }
+                        Button {
+                            text: "Create from current"
+                            enabled: newProfileField.text.length > 0
+                            onClicked: {
+                                controller.switch_profile(newProfileField.text);
+                                newProfileField.text = "";
+                                profileCombo.model = controller.list_profiles();
+                                profileCombo.currentIndex = Math.max(0, profileCombo.find(controller.profile));
+                            }
+                        }
+                    }
+                }
+            }
+
             GroupBox {
                 title: "Appearance"
                 Layout.fillWidth: true
@@ -47,19 +103,44 @@ Dialog {
                         ComboBox {
                             id: themeCombo
                             Layout.fillWidth: true
-                            model: ["System", "Light", "Dark"]
+                            // Bundled themes and the ones in the config's themes folder
+                            model: controller.list_themes()
+                            enabled: !settingsDialog.lockedReason("theme")
 
                             Component.onCompleted: {
-                                var theme = controller.theme;
-                                if (theme === "light")
-                                    currentIndex = 1;
-                                else if (theme === "dark")
-                                    currentIndex = 2;
-                                else
-                                    currentIndex = 0;
+                                currentIndex = Math.max(0, model.indexOf(controller.theme));
                             }
                         }
                     }
+                    LockNote {
+                        key: "theme"
+                    }
+
+                    RowLayout {
+                        Label {
+                            text: "WhatsApp:"
+                        }
+                        ComboBox {
+                            id: whatsappStrategyCombo
+                            Layout.fillWidth: true
+                            textRole: "text"
+                            valueRole: "value"
+                            // Native keeps working when WhatsApp renames its CSS classes,
+                            // the theme's own rules are only needed for the extras
+                            model: [
+                                { text: "Own light/dark mode + theme extras", value: "native" },
+                                { text: "Restyle with the theme's CSS", value: "css" }
+                            ]
+                            enabled: !settingsDialog.lockedReason("theme_strategies.whatsapp")
+
+                            Component.onCompleted: {
+                                currentIndex = Math.max(0, indexOfValue(controller.theme_strategy("whatsapp")));
+                            }
+                        }
+                    }
+                    LockNote {
+                        key: "theme_strategies.whatsapp"
+                    }
                 }
             }
 
@@ -83,9 +164,13 @@ Dialog {
                         }
                         Button {
                             text: "Browse..."
+                            enabled: !settingsDialog.lockedReason("download_path")
                             onClicked: folderDialog.open()
                         }
                     }
+                    LockNote {
+                        key: "download_path"
+                    }
                 }
             }
 
@@ -99,49 +184,92 @@ Dialog {
                     CheckBox {
                         id: enableNotificationsCheck
                         text: "Enable notifications"
+                        enabled: !settingsDialog.lockedReason("notification_settings.enabled")
                         Component.onCompleted: checked = controller.notifications_enabled
                         onClicked: controller.set_notifications_enabled(checked)
                     }
+                    LockNote {
+                        key: "notification_settings.enabled"
+                    }
 
                     CheckBox {
                         id: messageNotificationsCheck
                         text: "Show message notifications"
-                        enabled: enableNotificationsCheck.checked
+                        enabled: enableNotificationsCheck.checked && !settingsDialog.lockedReason("notification_settings.show_message_notifications")
                         Component.onCompleted: checked = controller.show_message_notifications
                         onClicked: controller.set_show_message_notifications(checked)
                     }
+                    LockNote {
+                        key: "notification_settings.show_message_notifications"
+                    }
 
                     CheckBox {
                         id: callNotificationsCheck
                         text: "Show call notifications"
-                        enabled: enableNotificationsCheck.checked
+                        enabled: enableNotificationsCheck.checked && !settingsDialog.lockedReason("notification_settings.show_call_notifications")
                         Component.onCompleted: checked = controller.show_call_notifications
                         onClicked: controller.set_show_call_notifications(checked)
                     }
+                    LockNote {
+                        key: "notification_settings.show_call_notifications"
+                    }
 
                     CheckBox {
                         id: soundEnabledCheck
                         text: "Enable notification sounds"
-                        enabled: enableNotificationsCheck.checked
+                        enabled: enableNotificationsCheck.checked && !settingsDialog.lockedReason("notification_settings.sound_enabled")
                         Component.onCompleted: checked = controller.notification_sound_enabled
                         onClicked: controller.set_notification_sound_enabled(checked)
                     }
+                    LockNote {
+                        key: "notification_settings.sound_enabled"
+                    }
+
+                    CheckBox {
+                        id: doNotDisturbCheck
+                        text: "Do not disturb (keep notifications in history only)"
+                        enabled: enableNotificationsCheck.checked && !settingsDialog.lockedReason("notification_settings.do_not_disturb")
+                        Component.onCompleted: checked = controller.do_not_disturb
+                        onClicked: controller.set_do_not_disturb(checked)
+                    }
+                    LockNote {
+                        key: "notification_settings.do_not_disturb"
+                    }
 
                     CheckBox {
                         id: showSenderCheck
                         text: "Show sender information"
-                        enabled: enableNotificationsCheck.checked
+                        enabled: enableNotificationsCheck.checked && !settingsDialog.lockedReason("notification_settings.show_sender")
                         Component.onCompleted: checked = controller.show_sender
                         onClicked: controller.set_show_sender(checked)
                     }
+                    LockNote {
+                        key: "notification_settings.show_sender"
+                    }
 
                     RowLayout {
                         Button {
                             text: "Test Notification"
                             enabled: enableNotificationsCheck.checked
-                            onClicked: controller.test_notification()
+                            onClicked: testResultLabel.text = controller.test_notification()
+                        }
+                        Label {
+                            id: testResultLabel
+                            Layout.fillWidth: true
+                            wrapMode: Text.Wrap
                         }
                     }
+
+                    Label {
+                        text: "Notification server: " + controller.notification_server
+                        Layout.fillWidth: true
+                        wrapMode: Text.Wrap
+                    }
+                    Label {
+                        text: "Supported features: " + (controller.notification_capabilities || "unknown")
+                        Layout.fillWidth: true
+                        wrapMode: Text.Wrap
+                    }
                 }
             }
 
@@ -174,6 +302,10 @@ Dialog {
         id: folderDialog
         title: "Choose Download Folder"
         currentFolder: {
+            if (controller && controller.download_path.length > 0) {
+                return "file://" + controller.download_path;
+            }
+
             var downloadPaths = StandardPaths.standardLocations(StandardPaths.DownloadLocation);
             if (downloadPaths.length > 0) {
                 return downloadPaths[0];
@@ -47,6 +47,16 @@ Rectangle {
                         : "transparent"
                     border.width: currentIndex === index ? 0 : 1
                     border.color: currentTheme === "dark" ? "#444" : "#bbb"
+                    // Rendered to disk as the notification icon fallback for this account
+                    function saveIcon() {
+                        var path = appController.tab_icon_file(index);
+                        if (path.length > 0) {
+                            tabIconRect.grabToImage(function (result) {
+                                result.saveToFile(path);
+                            }, Qt.size(64, 64));
+                        }
+                    }
+                    Component.onCompleted: Qt.callLater(saveIcon)
                     MouseArea {
                         id: tabMouseArea
                         anchors.fill: parent
@@ -113,6 +123,27 @@ Rectangle {
         Column {
             Layout.alignment: Qt.AlignHCenter
             spacing: 12
+            Button {
+                icon.name: "notifications"
+                onClicked: notificationCenter.open()
+                width: 40; height: 40
+                ToolTip.visible: hovered
+                ToolTip.text: "Notifications"
+                Rectangle {
+                    visible: appController && appController.notification_history.unread_count > 0
+                    anchors.top: parent.top
+                    anchors.right: parent.right
+                    width: 18; height: 18
+                    radius: 9
+                    color: "#e53935"
+                    Text {
+                        anchors.centerIn: parent
+                        text: appController ? Math.min(appController.notification_history.unread_count, 99) : ""
+                        font.pixelSize: 10
+                        color: "#fff"
+                    }
+                }
+            }
             Button {
                 icon.name: "settings"
                 onClicked: settingsDialog.open()
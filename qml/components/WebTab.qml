@@ -14,11 +14,15 @@ Item {
     id: webTabRoot
     property int index: 0
     property string url: ""
+    property string accountName: ""
+    // Web profile directory, shared by settings profiles listing the same account.
+    property string storage: "whatsapp_tab_" + index
     WebEngineProfilePrototype {
         id: profilePrototype
-        storageName: "whatsapp_tab_" + webTabRoot.index
+        storageName: webTabRoot.storage
         // persistentStoragePath: StandardPaths.writableLocation(StandardPaths.AppDataLocation) + "/WhatsApp-QT-Profile/" + getStorageName()
-        persistentStoragePath: StandardPaths.writableLocation(StandardPaths.AppDataLocation) + "/WhatsApp-QT-Profile/whatsapp_tab_" + webTabRoot.index
+        persistentStoragePath: webProfileRoot + "/" + webTabRoot.storage
+        cachePath: webCacheRoot + "/" + webTabRoot.storage
         persistentCookiesPolicy: WebEngineProfile.ForcePersistentCookies
 
     }
@@ -27,24 +31,56 @@ Item {
     function applyThemeCSS() {
         console.log("Applying theme to WebTab", webTabRoot.index);
 
-        var css = mainWindow.controller.get_theme_css();
-        if (css && css.length > 0) {
-            var script = `
+        // User themes can contain anything, so the CSS goes in as a JSON string
+        var css = JSON.stringify(mainWindow.controller.get_theme_css());
+        var script = `
             (function() {
                 var existingStyle = document.getElementById('app-theme-style');
                 if (existingStyle) { 
                     existingStyle.remove(); 
                 }
+                var css = ${css};
+                if (css.length === 0) {
+                    return;
+                }
                 var style = document.createElement('style');
                 style.id = 'app-theme-style';
-                style.textContent = \`${css}\`;
+                style.textContent = css;
                 document.head.appendChild(style);
             })();
         `;
-            webView.runJavaScript(script);
+        webView.runJavaScript(script);
+    }
+
+    // Switches WhatsApp's own light/dark mode, which reloads the page once
+    // when it changes. Empty when the theme restyles the page with CSS.
+    function applyColorScheme() {
+        var scheme = mainWindow.controller.native_scheme;
+        if (scheme !== "") {
+            webView.runJavaScript("window.__waqt && window.__waqt.setColorScheme(" + JSON.stringify(scheme) + ")");
         }
     }
 
+    function injectPageBridge() {
+        webView.runJavaScript(mainWindow.controller.get_page_bridge_script());
+    }
+
+    function runCallAction(action) {
+        webView.runJavaScript("window.__waqt && window.__waqt.callAction('" + action + "')", function (clicked) {
+            if (!clicked) {
+                console.warn("Could not find", action, "button in tab", webTabRoot.index);
+            }
+        });
+    }
+
+    function openChat(name) {
+        webView.runJavaScript("window.__waqt && window.__waqt.openChat(" + JSON.stringify(name) + ")", function (opened) {
+            if (!opened) {
+                console.warn("Could not find chat", name, "in tab", webTabRoot.index);
+            }
+        });
+    }
+
     function getStorageName() {
         return "whatsapp_tab_" + webTabRoot.index;
     }
@@ -76,12 +112,37 @@ Item {
             if (loadRequest.status === WebEngineView.LoadSucceededStatus) {
                 console.log("Page loaded successfully, applying theme...");
                 // applyThemeTimer.start();
+                injectPageBridge();
+                applyColorScheme();
+                applyThemeCSS();
+            }
+        }
+        onTitleChanged: mainWindow.controller.set_tab_title(webTabRoot.index, title)
+        onJavaScriptConsoleMessage: function (level, message, lineNumber, sourceID) {
+            if (message.startsWith("waqt:")) {
+                mainWindow.controller.handle_page_event(webTabRoot.index, webTabRoot.accountName, message);
+            }
+        }
+        onPermissionRequested: function (permission) {
+            if (permission.permissionType === WebEnginePermission.PermissionType.Notifications) {
+                permission.grant();
             }
         }
         onNewWindowRequested: function (request) {
             Qt.openUrlExternally(request.requestedUrl);
         }
     }
+
+    // Web notifications are shown natively by NotificationService
+    Connections {
+        target: webView.profile
+
+        function onPresentNotification(notification) {
+            mainWindow.controller.handle_web_notification(webTabRoot.index, webTabRoot.accountName,
+                                                          notification.title, notification.message);
+            notification.show();
+        }
+    }
     // Connections {
     //     target: appController
     //
@@ -15,13 +15,27 @@ ApplicationWindow {
     visible: true
     visibility: "Maximized"
     title: "WhatsApp Desktop - Multi Account"
+           + (appController.call_log.missed_count > 0
+              ? " (" + appController.call_log.missed_count + " missed calls)" : "")
     property alias controller: appController
     property string currentTheme: "system"
     property var downloadedFiles: []
     property string lastDownloadedFile: ""
-    property string webProfilePath: "WhatsApp-QT-Profile"
     property bool sidebarVisible: true
 
+    onActiveChanged: appController.set_window_active(active)
+    onVisibilityChanged: appController.set_window_visible(visibility !== Window.Hidden
+                                                          && visibility !== Window.Minimized)
+
+    // Delivers the missed-message digest on its schedule and logs calls
+    // whose end the page never reported
+    Timer {
+        interval: 60000
+        repeat: true
+        running: true
+        onTriggered: appController.check_digest()
+    }
+
     ListModel {
         id: tabsModel
     }
@@ -45,6 +59,11 @@ ApplicationWindow {
         Component.onCompleted: {
             console.log("Loading initial settings...");
             load_settings();
+            if (current_tab >= 0 && current_tab < tabsModel.count) {
+                stackLayout.currentIndex = current_tab;
+            }
+            init_notification_server();
+            init_color_scheme();
             currentTheme = appController.theme;
             applyTheme();
             console.log("Initial tabsModel count: " + tabsModel.count);
@@ -54,16 +73,20 @@ ApplicationWindow {
             }
         }
 
-        onTab_added: function (name, icon) {
+        onTab_added: function (name, icon, storage) {
             console.log("QML onTab_added: Adding tab to model ->", name);
             tabsModel.append({
                 name: name,
                 icon: icon,
+                storage: storage,
                 // url: "https://browserleaks.com/http2"
                 url: "https://web.whatsapp.com"
             });
             stackLayout.currentIndex = tabsModel.count - 1;
         }
+        onTabs_cleared: {
+            tabsModel.clear();
+        }
         onTab_removed: function (index) {
             if (tabsModel.count > 1) {
                 tabsModel.remove(index);
@@ -77,46 +100,69 @@ ApplicationWindow {
             if (index < tabsModel.count) {
                 var oldIcon = tabsModel.get(index).icon;
                 var oldUrl = tabsModel.get(index).url;
-                tabsModel.set(index, {name: newName, icon: oldIcon, url: oldUrl});
+                var storage = tabsModel.get(index).storage;
+                tabsModel.set(index, {name: newName, icon: oldIcon, storage: storage, url: oldUrl});
+            }
+        }
+        onRaise_window: function (index) {
+            if (index >= 0 && index < tabsModel.count) {
+                stackLayout.currentIndex = index;
+                appController.set_current_tab(index);
+            }
+            mainWindow.show();
+            mainWindow.raise();
+            mainWindow.requestActivate();
+        }
+        onOpen_chat_requested: function (index, chat) {
+            var delegateItem = stackLayout.children[index];
+            if (delegateItem && delegateItem.openChat) {
+                delegateItem.openChat(chat);
+            }
+        }
+        onLoad_failed: function (error) {
+            settingsErrorDialog.text = error;
+            settingsErrorDialog.open();
+        }
+        onValidation_failed: function (field, message) {
+            // Several fields can fail at once, list them all.
+            var line = field + ": " + message;
+            validationDialog.text = validationDialog.visible ? validationDialog.text + "\n" + line : line;
+            validationDialog.open();
+        }
+        onSetting_rejected: function (key, reason) {
+            settingRejectedDialog.text = key + " can not be changed: " + reason;
+            settingRejectedDialog.open();
+        }
+        onCall_action_requested: function (index, action) {
+            var delegateItem = stackLayout.children[index];
+            if (delegateItem && delegateItem.runCallAction) {
+                delegateItem.runCallAction(action);
             }
         }
         onTheme_changed: {
             console.log("Theme changed signal received in QML, new theme:", appController.theme);
 
-            var newTheme = appController.theme;
-            if (currentTheme !== newTheme) {
-                currentTheme = newTheme;
-                sidebar.currentTheme = currentTheme;
-                applyTheme();
-            }
-
+            // Re-applied even for the same name, the theme file may have changed.
+            currentTheme = appController.theme;
+            applyTheme();
         }
     }
 
     function applyTheme() {
         console.log("Applying theme:", currentTheme);
 
-        switch (currentTheme) {
-            case "dark":
-                mainWindow.color = "#2d2d2d";
-                break;
-            case "light":
-                mainWindow.color = "#ffffff";
-                break;
-            default:
-                mainWindow.color = palette.window;
-        }
-
-        // Generate CSS and apply to all web views in one go
-        var css = appController.get_theme_css();
-        if (css && css.length > 0) {
-            console.log("Applying CSS to", tabsModel.count, "web views");
-            for (var i = 0; i < tabsModel.count; i++) {
-                var delegateItem = stackLayout.children[i];
-                if (delegateItem && delegateItem.applyThemeCSS) {
-                    console.log("Applying theme to web view", i);
-                    delegateItem.applyThemeCSS();
-                }
+        mainWindow.color = appController.theme_window_color !== ""
+                ? appController.theme_window_color : palette.window;
+
+        // Apply the CSS to all web views in one go, an empty one removes
+        // the previous theme's styles
+        console.log("Applying CSS to", tabsModel.count, "web views");
+        for (var i = 0; i < tabsModel.count; i++) {
+            var delegateItem = stackLayout.children[i];
+            if (delegateItem && delegateItem.applyThemeCSS) {
+                console.log("Applying theme to web view", i);
+                delegateItem.applyColorScheme();
+                delegateItem.applyThemeCSS();
             }
         }
     }
@@ -129,7 +175,7 @@ ApplicationWindow {
         Components.Sidebar {
             id: sidebar
             visible: sidebarVisible
-            currentTheme: mainWindow.currentTheme
+            currentTheme: appController.theme_variant
             appController: appController
             stackLayout: stackLayout
             tabsModel: tabsModel
@@ -177,11 +223,37 @@ ApplicationWindow {
                     id: webTabDelegate
                     index: model.index
                     url: model.url
+                    storage: model.storage
+                    accountName: model.name
                 }
             }
         }
     }
 
+    MessageDialog {
+        id: settingsErrorDialog
+        title: "Settings could not be loaded"
+        text: ""
+    }
+
+    MessageDialog {
+        id: validationDialog
+        title: "Invalid settings"
+        text: ""
+    }
+
+    MessageDialog {
+        id: settingRejectedDialog
+        title: "Setting is locked"
+        text: ""
+    }
+
+    Components.NotificationCenter {
+        id: notificationCenter
+        controller: appController
+        currentTheme: appController.theme_variant
+    }
+
     MouseArea {
         id: showSidebarMouseArea
         anchors.left: parent.left
@@ -0,0 +1,179 @@
+// Injected into every WhatsApp Web tab once the page has loaded.
+// Page state is reported back to Rust through console messages prefixed
+// with "waqt:" followed by a JSON payload (see src/page_bridge.rs).
+(function () {
+    if (window.__waqt) {
+        return;
+    }
+
+    function emit(event) {
+        console.log("waqt:" + JSON.stringify(event));
+    }
+
+    // --- Incoming calls ---
+    var ringing = null;
+
+    function findIncomingCall() {
+        var nodes = document.querySelectorAll('[role="dialog"], [data-testid="incoming-call"]');
+        for (var i = 0; i < nodes.length; i++) {
+            var text = nodes[i].innerText || "";
+            var match = /incoming (voice|video) call/i.exec(text);
+            if (match) {
+                var lines = text.split("\n")
+                    .map(function (line) { return line.trim(); })
+                    .filter(function (line) { return line.length > 0 && !/incoming .* call/i.test(line); });
+                return {
+                    node: nodes[i],
+                    kind: match[1].toLowerCase(),
+                    caller: lines.length > 0 ? lines[0] : ""
+                };
+            }
+        }
+        return null;
+    }
+
+    function scanCall() {
+        var found = findIncomingCall();
+        if (found && !ringing) {
+            ringing = found;
+            emit({ type: "call", state: "ringing", kind: found.kind, caller: found.caller });
+        } else if (!found && ringing) {
+            emit({
+                type: "call",
+                state: "ended",
+                kind: ringing.kind,
+                caller: ringing.caller,
+                outcome: ringing.outcome || null
+            });
+            ringing = null;
+        }
+    }
+
+    function buttonLabel(button) {
+        return (button.getAttribute("aria-label") || button.innerText || "").toLowerCase();
+    }
+
+    // Remember whether the ringing call was picked up or rejected, whether
+    // from the page itself or through callAction().
+    document.addEventListener("click", function (event) {
+        if (!ringing || !ringing.node.contains(event.target)) {
+            return;
+        }
+        var button = event.target.closest('button, [role="button"]');
+        if (!button) {
+            return;
+        }
+        var label = buttonLabel(button);
+        if (/accept|answer/.test(label)) {
+            ringing.outcome = "answered";
+        } else if (/decline|reject/.test(label)) {
+            ringing.outcome = "declined";
+        }
+    }, true);
+
+    function clickCallButton(labels) {
+        if (!ringing) {
+            return false;
+        }
+        var buttons = ringing.node.querySelectorAll('button, [role="button"]');
+        for (var i = 0; i < buttons.length; i++) {
+            var label = buttonLabel(buttons[i]);
+            for (var j = 0; j < labels.length; j++) {
+                if (label.indexOf(labels[j]) !== -1) {
+                    buttons[i].click();
+                    return true;
+                }
+            }
+        }
+        return false;
+    }
+
+    // --- Avatars ---
+    var avatars = {};
+
+    function scanAvatars() {
+        var rows = document.querySelectorAll('#pane-side [role="listitem"], #pane-side [role="row"]');
+        for (var i = 0; i < rows.length; i++) {
+            var title = rows[i].querySelector('span[title]');
+            var img = rows[i].querySelector('img[src^="https://"]');
+            if (!title || !img) {
+                continue;
+            }
+            var chat = title.getAttribute("title");
+            if (avatars[chat] !== img.src) {
+                avatars[chat] = img.src;
+                emit({ type: "avatar", chat: chat, url: img.src });
+            }
+        }
+    }
+
+    // --- Page scanning ---
+    var scanPending = false;
+
+    function scheduleScan() {
+        if (scanPending) {
+            return;
+        }
+        scanPending = true;
+        setTimeout(function () {
+            scanPending = false;
+            scanCall();
+            scanAvatars();
+        }, 250);
+    }
+
+    // --- Chats ---
+    function openChat(name) {
+        var chats = document.querySelectorAll('#pane-side span[title]');
+        for (var i = 0; i < chats.length; i++) {
+            if (chats[i].getAttribute("title") === name) {
+                var target = chats[i].closest('[role="listitem"], [role="row"]') || chats[i];
+                target.dispatchEvent(new MouseEvent("mousedown", { bubbles: true }));
+                target.click();
+                return true;
+            }
+        }
+        return false;
+    }
+
+    // --- Colour scheme ---
+    // WhatsApp Web reads its theme from localStorage while loading, so a
+    // change takes a reload. Reloads at most once per scheme and session in
+    // case WhatsApp stops honouring the stored value.
+    function setColorScheme(scheme) {
+        var systemMode = scheme === "system" ? "true" : "false";
+        var theme = scheme === "system" ? null : JSON.stringify(scheme);
+        if (localStorage.getItem("system-theme-mode") === systemMode
+                && (theme === null || localStorage.getItem("theme") === theme)) {
+            return false;
+        }
+        localStorage.setItem("system-theme-mode", systemMode);
+        if (theme !== null) {
+            localStorage.setItem("theme", theme);
+        }
+        if (sessionStorage.getItem("waqt-scheme-reload") === scheme) {
+            return false;
+        }
+        sessionStorage.setItem("waqt-scheme-reload", scheme);
+        location.reload();
+        return true;
+    }
+
+    window.__waqt = {
+        openChat: openChat,
+        setColorScheme: setColorScheme,
+        callAction: function (action) {
+            if (action === "answer") {
+                return clickCallButton(["accept", "answer"]);
+            }
+            if (action === "decline") {
+                return clickCallButton(["decline", "reject"]);
+            }
+            return false;
+        }
+    };
+
+    new MutationObserver(scheduleScan).observe(document.body, { childList: true, subtree: true });
+    scanCall();
+    scanAvatars();
+})();
@@ -0,0 +1,233 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::runtime::runtime;
+
+const INDEX_FILE: &str = "index.json";
+const MAX_IMAGE_BYTES: usize = 512 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct AvatarLimits {
+    /// Total size of the cached avatars of one account.
+    pub max_account_bytes: u64,
+    /// Avatars older than this are ignored and fetched again, 0 = never expire.
+    pub max_age_days: u32,
+}
+
+impl Default for AvatarLimits {
+    fn default() -> Self {
+        Self {
+            max_account_bytes: 20 * 1024 * 1024,
+            max_age_days: 14,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AvatarEntry {
+    file: String,
+    url: String,
+    fetched_at: i64,
+    size: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AccountIndex {
+    #[serde(default)]
+    next_file: u64,
+    #[serde(default)]
+    entries: HashMap<String, AvatarEntry>,
+}
+
+/// Sender avatars captured from the page, cached per account under
+/// `<cache>/WhatsApp-QT/avatars/<storage>/`, keyed like the web profiles so
+/// they stay with the account when tabs move.
+#[derive(Clone)]
+pub struct AvatarCache {
+    root: PathBuf,
+    limits: Arc<Mutex<AvatarLimits>>,
+    indexes: Arc<Mutex<HashMap<String, AccountIndex>>>,
+}
+
+impl AvatarCache {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            limits: Arc::new(Mutex::new(AvatarLimits::default())),
+            indexes: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn set_limits(&self, limits: AvatarLimits) {
+        *self.limits.lock().unwrap() = limits;
+    }
+
+    fn account_dir(&self, storage: &str) -> PathBuf {
+        self.root.join("avatars").join(storage)
+    }
+
+    /// Image file rendered from the account's sidebar icon by QML.
+    pub fn tab_icon_file(&self, storage: &str) -> PathBuf {
+        self.root.join("tab_icons").join(format!("{}.png", storage))
+    }
+
+    /// Best icon for a notification: the sender's avatar, else the account's
+    /// tab icon.
+    pub fn icon_for(&self, storage: &str, chat: &str) -> Option<PathBuf> {
+        self.lookup(storage, chat).or_else(|| {
+            let tab_icon = self.tab_icon_file(storage);
+            tab_icon.exists().then_some(tab_icon)
+        })
+    }
+
+    pub fn lookup(&self, storage: &str, chat: &str) -> Option<PathBuf> {
+        let max_age_days = self.limits.lock().unwrap().max_age_days;
+        let mut indexes = self.indexes.lock().unwrap();
+        let index = self.load_index(&mut indexes, storage);
+        let entry = index.entries.get(chat)?;
+        if is_expired(entry, max_age_days) {
+            return None;
+        }
+        let path = self.account_dir(storage).join(&entry.file);
+        path.exists().then_some(path)
+    }
+
+    /// Records the avatar URL the page shows for `chat`, downloading it in
+    /// the background when it is new, changed or expired.
+    pub fn update(&self, storage: String, chat: String, url: String) {
+        {
+            let max_age_days = self.limits.lock().unwrap().max_age_days;
+            let mut indexes = self.indexes.lock().unwrap();
+            let index = self.load_index(&mut indexes, &storage);
+            if let Some(entry) = index.entries.get(&chat) {
+                if entry.url == url && !is_expired(entry, max_age_days) {
+                    return;
+                }
+            }
+        }
+
+        let cache = self.clone();
+        runtime().spawn(async move {
+            match download(&url).await {
+                Ok(bytes) => cache.store(&storage, chat, url, &bytes),
+                Err(e) => println!("Failed to fetch avatar for {}: {}", chat, e),
+            }
+        });
+    }
+
+    fn store(&self, storage: &str, chat: String, url: String, bytes: &[u8]) {
+        let dir = self.account_dir(storage);
+        if let Err(e) = fs::create_dir_all(&dir) {
+            println!("Failed to create avatar cache {}: {}", dir.display(), e);
+            return;
+        }
+
+        let limits = *self.limits.lock().unwrap();
+        let mut indexes = self.indexes.lock().unwrap();
+        let index = self.load_index(&mut indexes, storage);
+
+        let file = match index.entries.get(&chat) {
+            Some(entry) => entry.file.clone(),
+            None => {
+                index.next_file += 1;
+                format!("{}.img", index.next_file)
+            }
+        };
+        if let Err(e) = fs::write(dir.join(&file), bytes) {
+            println!("Failed to write avatar for {}: {}", chat, e);
+            return;
+        }
+        index.entries.insert(
+            chat,
+            AvatarEntry {
+                file,
+                url,
+                fetched_at: Local::now().timestamp(),
+                size: bytes.len() as u64,
+            },
+        );
+
+        evict(&dir, index, limits.max_account_bytes);
+        save_index(&dir, index);
+    }
+
+    fn load_index<'a>(
+        &self,
+        indexes: &'a mut HashMap<String, AccountIndex>,
+        storage: &str,
+    ) -> &'a mut AccountIndex {
+        indexes.entry(storage.to_string()).or_insert_with(|| {
+            let dir = self.account_dir(storage);
+            let mut index: AccountIndex = fs::read_to_string(dir.join(INDEX_FILE))
+                .ok()
+                .and_then(|data| serde_json::from_str(&data).ok())
+                .unwrap_or_default();
+            let max_age_days = self.limits.lock().unwrap().max_age_days;
+            index.entries.retain(|_, entry| {
+                let keep = !is_expired(entry, max_age_days);
+                if !keep {
+                    let _ = fs::remove_file(dir.join(&entry.file));
+                }
+                keep
+            });
+            index
+        })
+    }
+}
+
+fn is_expired(entry: &AvatarEntry, max_age_days: u32) -> bool {
+    max_age_days > 0
+        && Local::now().timestamp() - entry.fetched_at > i64::from(max_age_days) * 86_400
+}
+
+/// Removes the oldest avatars until the account fits in `max_bytes`.
+fn evict(dir: &Path, index: &mut AccountIndex, max_bytes: u64) {
+    let mut total: u64 = index.entries.values().map(|e| e.size).sum();
+    while total > max_bytes {
+        let Some(oldest) = index
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.fetched_at)
+            .map(|(chat, _)| chat.clone())
+        else {
+            break;
+        };
+        if let Some(entry) = index.entries.remove(&oldest) {
+            let _ = fs::remove_file(dir.join(&entry.file));
+            total -= entry.size;
+        }
+    }
+}
+
+fn save_index(dir: &Path, index: &AccountIndex) {
+    match serde_json::to_string(index) {
+        Ok(json) => {
+            if let Err(e) = fs::write(dir.join(INDEX_FILE), json) {
+                println!("Failed to save avatar index: {}", e);
+            }
+        }
+        Err(e) => println!("Failed to serialize avatar index: {}", e),
+    }
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    if !url.starts_with("https://") {
+        return Err("only https avatars are fetched".into());
+    }
+    let response = reqwest::get(url).await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_IMAGE_BYTES as u64)
+    {
+        return Err("avatar too large".into());
+    }
+    let bytes = response.bytes().await?;
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err("avatar too large".into());
+    }
+    Ok(bytes.to_vec())
+}
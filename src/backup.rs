@@ -0,0 +1,462 @@
+use chrono::{Datelike, Local, NaiveDateTime};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+
+use crate::paths;
+use crate::profiles;
+use crate::runtime::runtime;
+use crate::settings;
+
+/// Snapshots live in `<data>/backups/<set>/<timestamp>.tar.gz`.
+pub const BACKUP_DIR: &str = "backups";
+/// Snapshot set holding the config directory.
+pub const SETTINGS_SET: &str = "settings";
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+const ARCHIVE_EXTENSION: &str = ".tar.gz";
+/// How often the schedule is checked; skipped accounts are retried then.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// An account written to more recently than this is considered in use.
+const QUIET_PERIOD: Duration = Duration::from_secs(120);
+
+const USAGE: &str = "Usage: whatsapp-qt backup <command>
+
+Commands:
+  list                        Show the snapshots of the settings and each account
+  now                         Snapshot the settings and the active profile's
+                              accounts now
+  restore <set> [snapshot]    Restore the settings or an account (e.g. whatsapp_tab_0)
+                              from its newest or the given snapshot";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Minimum time between two snapshots of the same account.
+    #[serde(default = "default_interval_hours")]
+    pub interval_hours: u32,
+    /// Newest snapshot of each of this many days is kept.
+    #[serde(default = "default_keep_daily")]
+    pub keep_daily: usize,
+    /// Newest snapshot of each of this many weeks is kept.
+    #[serde(default = "default_keep_weekly")]
+    pub keep_weekly: usize,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_interval_hours() -> u32 {
+    24
+}
+
+fn default_keep_daily() -> usize {
+    7
+}
+
+fn default_keep_weekly() -> usize {
+    4
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_hours: default_interval_hours(),
+            keep_daily: default_keep_daily(),
+            keep_weekly: default_keep_weekly(),
+        }
+    }
+}
+
+enum Command {
+    Configure {
+        settings: BackupSettings,
+        accounts: Vec<String>,
+    },
+}
+
+/// Snapshots the config directory and the accounts' web profiles from a
+/// background task, so a lost profile doesn't mean scanning every QR code
+/// again.
+pub struct BackupScheduler {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl BackupScheduler {
+    pub fn start() -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        runtime().spawn(run(receiver));
+        Self { commands }
+    }
+
+    /// `accounts` are the web profile directories to keep snapshots of.
+    pub fn configure(&self, settings: BackupSettings, accounts: Vec<String>) {
+        let _ = self
+            .commands
+            .send(Command::Configure { settings, accounts });
+    }
+}
+
+async fn run(mut receiver: mpsc::UnboundedReceiver<Command>) {
+    let mut config: Option<(BackupSettings, Vec<String>)> = None;
+    loop {
+        tokio::select! {
+            command = receiver.recv() => match command {
+                Some(Command::Configure { settings, accounts }) => {
+                    config = Some((settings, accounts));
+                }
+                None => break,
+            },
+            _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+        }
+
+        let Some((settings, accounts)) = config.clone() else {
+            continue;
+        };
+        if !settings.enabled {
+            continue;
+        }
+        let due = tokio::task::spawn_blocking(move || snapshot_due(&settings, &accounts, false));
+        if let Err(e) = due.await {
+            println!("Backup task failed: {}", e);
+        }
+    }
+}
+
+fn backup_root() -> Option<PathBuf> {
+    paths::data_dir().map(|data| data.join(BACKUP_DIR))
+}
+
+/// Snapshots every set whose newest snapshot is older than the interval,
+/// or all of them with `force`, and prunes old snapshots.
+fn snapshot_due(settings: &BackupSettings, accounts: &[String], force: bool) {
+    let (Some(root), Some(cfg), Some(web_profiles)) =
+        (backup_root(), paths::config_dir(), paths::profiles_dir())
+    else {
+        return;
+    };
+    let interval = chrono::Duration::hours(i64::from(settings.interval_hours));
+    let due = |set: &str| {
+        force
+            || snapshots(&root.join(set))
+                .first()
+                .is_none_or(|(taken, _)| Local::now().naive_local() - *taken >= interval)
+    };
+
+    if due(SETTINGS_SET) {
+        // The config directory is small and written atomically.
+        if let Err(e) = snapshot(&cfg, &root.join(SETTINGS_SET)) {
+            println!("Failed to back up settings: {}", e);
+        }
+    }
+    for account in accounts {
+        let dir = web_profiles.join(account);
+        if !dir.is_dir() || !due(account) {
+            continue;
+        }
+        // Taken while the app runs, a profile that is written to meanwhile
+        // is tried again on the next run.
+        let started = SystemTime::now();
+        match snapshot(&dir, &root.join(account)) {
+            // Files that changed while archiving make the snapshot unusable.
+            Ok(path) if newest_change(&dir).is_some_and(|changed| changed > started) => {
+                println!("Discarding backup of {}, it changed meanwhile", account);
+                let _ = fs::remove_file(path);
+            }
+            Ok(path) => println!("Backed up {} to {}", account, path.display()),
+            Err(e) => println!("Failed to back up {}: {}", account, e),
+        }
+    }
+
+    let mut sets: Vec<&str> = accounts.iter().map(String::as_str).collect();
+    sets.push(SETTINGS_SET);
+    for set in sets {
+        prune(&root.join(set), settings.keep_daily, settings.keep_weekly);
+    }
+}
+
+/// Whether a web profile was written to in the last `QUIET_PERIOD`, i.e. an
+/// instance of the app has it open.
+pub fn in_use(dir: &Path, now: SystemTime) -> bool {
+    newest_change(dir).is_some_and(|changed| {
+        now.duration_since(changed)
+            .map_or(true, |age| age < QUIET_PERIOD)
+    })
+}
+
+fn newest_change(dir: &Path) -> Option<SystemTime> {
+    let mut newest = fs::symlink_metadata(dir).and_then(|m| m.modified()).ok();
+    for entry in fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+    {
+        let changed = match entry.file_type() {
+            Ok(kind) if kind.is_dir() => newest_change(&entry.path()),
+            _ => entry.metadata().and_then(|m| m.modified()).ok(),
+        };
+        newest = newest.max(changed);
+    }
+    newest
+}
+
+/// Archives the contents of `dir` into a new snapshot in `set_dir`. The
+/// archive is written under a temporary name and renamed when complete.
+fn snapshot(dir: &Path, set_dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(set_dir)?;
+    let name = format!(
+        "{}{}",
+        Local::now().format(TIMESTAMP_FORMAT),
+        ARCHIVE_EXTENSION
+    );
+    let path = set_dir.join(&name);
+    let partial = set_dir.join(format!("{}.partial", name));
+
+    let result = (|| {
+        let mut archive = tar::Builder::new(GzEncoder::new(
+            File::create(&partial)?,
+            Compression::default(),
+        ));
+        archive.follow_symlinks(false);
+        archive.append_dir_all(".", dir)?;
+        archive.into_inner()?.finish()?.sync_all()
+    })();
+    match result {
+        Ok(()) => {
+            fs::rename(&partial, &path)?;
+            Ok(path)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// Snapshots in `set_dir`, newest first.
+fn snapshots(set_dir: &Path) -> Vec<(NaiveDateTime, PathBuf)> {
+    let mut found: Vec<(NaiveDateTime, PathBuf)> = fs::read_dir(set_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let stamp = name.strip_suffix(ARCHIVE_EXTENSION)?;
+            let taken = NaiveDateTime::parse_from_str(stamp, TIMESTAMP_FORMAT).ok()?;
+            Some((taken, entry.path()))
+        })
+        .collect();
+    found.sort_by(|a, b| b.0.cmp(&a.0));
+    found
+}
+
+/// Keeps the newest snapshot of each of the last `keep_daily` days and of
+/// each of the last `keep_weekly` weeks that have snapshots.
+fn prune(set_dir: &Path, keep_daily: usize, keep_weekly: usize) {
+    let all = snapshots(set_dir);
+    let mut keep = HashSet::new();
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    for (taken, path) in &all {
+        if days.len() < keep_daily && days.insert(taken.date()) {
+            keep.insert(path.clone());
+        }
+        let week = taken.iso_week();
+        if weeks.len() < keep_weekly && weeks.insert((week.year(), week.week())) {
+            keep.insert(path.clone());
+        }
+    }
+    // Never remove the only copy, whatever the retention says.
+    if let Some((_, newest)) = all.first() {
+        keep.insert(newest.clone());
+    }
+    for (_, path) in all.into_iter().filter(|(_, path)| !keep.contains(path)) {
+        if let Err(e) = fs::remove_file(&path) {
+            println!("Failed to remove old backup {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Runs `whatsapp-qt backup ...` without starting Qt.
+pub fn run_command(args: &[String]) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let root = backup_root().ok_or("Could not find data directory")?;
+    match args.as_slice() {
+        ["list"] => {
+            let mut sets: Vec<PathBuf> = fs::read_dir(&root)
+                .into_iter()
+                .flatten()
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect();
+            sets.sort();
+            if sets.is_empty() {
+                println!("No backups in {}", root.display());
+            }
+            for set in sets {
+                let name = set.file_name().unwrap_or_default().to_string_lossy();
+                println!("{}:", name);
+                for (taken, _) in snapshots(&set) {
+                    println!("  {}", taken.format(TIMESTAMP_FORMAT));
+                }
+            }
+            Ok(())
+        }
+        ["now"] => {
+            // The same accounts and retention the app uses for the profile.
+            let profile = profiles::startup_profile();
+            let dir = profiles::dir(&profile).ok_or("Could not find config directory")?;
+            let settings = settings::load(&dir)
+                .map_err(|e| format!("Failed to load profile {}: {}", profile, e))?
+                .unwrap_or_default();
+            let accounts: Vec<String> = settings
+                .tabs
+                .iter()
+                .map(|tab| tab.storage.clone())
+                .collect();
+            snapshot_due(&settings.backup, &accounts, true);
+            Ok(())
+        }
+        ["restore", set] => restore(&root, set, None),
+        ["restore", set, snapshot] => restore(&root, set, Some(snapshot)),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// Replaces the settings or an account with a snapshot. The current files
+/// are moved aside, not deleted.
+fn restore(root: &Path, set: &str, snapshot: Option<&str>) -> Result<(), String> {
+    if set.is_empty() || set.contains(['/', '\\']) || set.starts_with('.') {
+        return Err(format!("Invalid backup name {:?}", set));
+    }
+    let all = snapshots(&root.join(set));
+    let (taken, archive) = match snapshot {
+        Some(wanted) => all
+            .into_iter()
+            .find(|(taken, _)| taken.format(TIMESTAMP_FORMAT).to_string() == wanted)
+            .ok_or_else(|| format!("No snapshot {} of {}", wanted, set))?,
+        None => all
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("No backups of {}", set))?,
+    };
+    let target = if set == SETTINGS_SET {
+        paths::config_dir().ok_or("Could not find config directory")?
+    } else {
+        paths::profiles_dir()
+            .ok_or("Could not find data directory")?
+            .join(set)
+    };
+    if target.is_dir() && in_use(&target, SystemTime::now()) {
+        return Err(format!(
+            "{} is in use, close WhatsApp-QT before restoring it",
+            set
+        ));
+    }
+
+    let stamp = Local::now().format(TIMESTAMP_FORMAT);
+    let staging = root.join(format!(".restore-{}-{}", set, stamp));
+    let unpacked = File::open(&archive)
+        .and_then(|file| tar::Archive::new(GzDecoder::new(file)).unpack(&staging))
+        .map_err(|e| format!("Failed to unpack {}: {}", archive.display(), e));
+    if let Err(e) = unpacked {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    let aside = target.with_file_name(format!(
+        "{}.before-restore-{}",
+        target.file_name().unwrap_or_default().to_string_lossy(),
+        stamp
+    ));
+    if target.exists() {
+        paths::move_path(&target, &aside)
+            .map_err(|e| format!("Failed to move {} aside: {}", target.display(), e))?;
+        println!("Previous files moved to {}", aside.display());
+    }
+    paths::move_path(&staging, &target)
+        .map_err(|e| format!("Failed to restore {}: {}", target.display(), e))?;
+    println!(
+        "Restored {} from {}",
+        set,
+        taken.format("%Y-%m-%d %H:%M:%S")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("whatsapp-qt-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn add_snapshots(dir: &Path, stamps: &[&str]) {
+        for stamp in stamps {
+            File::create(dir.join(format!("{}{}", stamp, ARCHIVE_EXTENSION))).unwrap();
+        }
+    }
+
+    fn remaining(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_of_each_day_and_week() {
+        let dir = set_dir("prune");
+        add_snapshots(
+            &dir,
+            &[
+                "20260105-120000",
+                "20260105-100000",
+                "20260104-090000",
+                "20251228-090000",
+                "20251220-090000",
+            ],
+        );
+        File::create(dir.join("notes.txt")).unwrap();
+
+        prune(&dir, 2, 3);
+
+        assert_eq!(
+            remaining(&dir),
+            [
+                "20251228-090000.tar.gz",
+                "20260104-090000.tar.gz",
+                "20260105-120000.tar.gz",
+                "notes.txt",
+            ]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn prune_never_removes_the_newest_snapshot() {
+        let dir = set_dir("prune-newest");
+        add_snapshots(&dir, &["20260105-120000", "20260104-090000"]);
+
+        prune(&dir, 0, 0);
+
+        assert_eq!(remaining(&dir), ["20260105-120000.tar.gz"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
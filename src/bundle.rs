@@ -0,0 +1,626 @@
+use chacha20poly1305::aead::KeyInit;
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::{ChaCha20Poly1305, Key};
+use chrono::Local;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use rand::Rng;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::SystemTime;
+
+use crate::backup;
+use crate::css;
+use crate::paths;
+use crate::profiles;
+use crate::secrets;
+use crate::settings::{self, AppSettings, SETTINGS_FILE};
+use crate::settings_writer::SettingsWriter;
+use crate::validation;
+
+const MAGIC: &[u8; 8] = b"WAQTBDL1";
+const SALT_LEN: usize = 16;
+/// Nonce prefix of the STREAM construction, the rest is the chunk counter.
+const STREAM_NONCE_LEN: usize = 7;
+const CHUNK_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const MANIFEST_FILE: &str = "manifest.json";
+const FORMAT_VERSION: u32 = 1;
+/// Archive folders for the config directory and the account web profiles.
+const CONFIG_ENTRY: &str = "config";
+const ACCOUNTS_ENTRY: &str = "accounts";
+/// Config subdirectories copied besides the settings.
+const EXTRA_CONFIG_DIRS: &[&str] = &[css::THEMES_DIR];
+
+const USAGE: &str = "Usage: whatsapp-qt bundle <command>
+
+Commands:
+  export <file> [--accounts]         Write settings, profiles and themes to an
+                                     encrypted bundle, --accounts adds the logins
+  import <file> [--merge|--replace]  Add the bundle to this setup (default) or
+                                     replace the settings and accounts with it";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: u32,
+    created_at: String,
+    /// Settings profiles in the bundle, `default` included.
+    profiles: Vec<String>,
+    /// Web profile directories in the bundle.
+    accounts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImportMode {
+    Merge,
+    Replace,
+}
+
+/// Runs `whatsapp-qt bundle ...` without starting Qt.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["export", file] => export(Path::new(file), false),
+        ["export", file, "--accounts"] | ["export", "--accounts", file] => {
+            export(Path::new(file), true)
+        }
+        ["import", file] | ["import", file, "--merge"] => {
+            import(Path::new(file), ImportMode::Merge)
+        }
+        ["import", file, "--replace"] => import(Path::new(file), ImportMode::Replace),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn export(file: &Path, with_accounts: bool) -> Result<(), String> {
+    let cfg = paths::config_dir().ok_or("Could not find config directory")?;
+    let passphrase = secrets::prompt_passphrase("Bundle passphrase: ", true)?;
+    let mut salt = [0u8; SALT_LEN];
+    rand::rng().fill(&mut salt[..]);
+    let mut nonce = [0u8; STREAM_NONCE_LEN];
+    rand::rng().fill(&mut nonce[..]);
+    let key = secrets::derive_key(&passphrase, &salt)?;
+
+    let io_error = |e: io::Error| format!("Failed to write {}: {}", file.display(), e);
+    let mut out = BufWriter::new(File::create(file).map_err(io_error)?);
+    out.write_all(MAGIC).map_err(io_error)?;
+    out.write_all(&salt).map_err(io_error)?;
+    out.write_all(&nonce).map_err(io_error)?;
+    let encryptor = EncryptorBE32::from_aead(
+        ChaCha20Poly1305::new(Key::from_slice(key.expose_secret())),
+        GenericArray::from_slice(&nonce),
+    );
+    let sealed = SealingWriter {
+        inner: out,
+        encryptor: Some(encryptor),
+        buffer: Vec::with_capacity(CHUNK_LEN),
+    };
+    let mut archive = tar::Builder::new(GzEncoder::new(sealed, Compression::default()));
+    // Web profiles hold lock symlinks that must not be followed.
+    archive.follow_symlinks(false);
+
+    let mut manifest = Manifest {
+        format: FORMAT_VERSION,
+        created_at: Local::now().to_rfc3339(),
+        profiles: Vec::new(),
+        accounts: Vec::new(),
+    };
+    let mut storages = HashSet::new();
+    for name in profiles::list() {
+        let Some(dir) = profiles::dir(&name) else {
+            continue;
+        };
+        let Some(mut profile) = settings::load(&dir).map_err(|e| e.to_string())? else {
+            continue;
+        };
+        // The bundle is encrypted as a whole, the key of this machine
+        // would be useless on the next one.
+        profile.decrypt_fields().map_err(|e| e.to_string())?;
+        profile.encryption = None;
+        profile.download_path = paths::relative_to_home(&profile.download_path);
+        storages.extend(profile.tabs.iter().map(|tab| tab.storage.clone()));
+        let json = serde_json::to_vec_pretty(&profile).map_err(|e| e.to_string())?;
+        let relative = dir.strip_prefix(&cfg).unwrap_or(Path::new(""));
+        let entry = Path::new(CONFIG_ENTRY).join(relative).join(SETTINGS_FILE);
+        append_bytes(&mut archive, &entry, &json).map_err(io_error)?;
+        manifest.profiles.push(name);
+    }
+    for extra in EXTRA_CONFIG_DIRS {
+        let dir = cfg.join(extra);
+        if dir.is_dir() {
+            archive
+                .append_dir_all(Path::new(CONFIG_ENTRY).join(extra), &dir)
+                .map_err(io_error)?;
+        }
+    }
+    if with_accounts {
+        let web_profiles = paths::profiles_dir().ok_or("Could not find data directory")?;
+        let mut storages: Vec<String> = storages.into_iter().collect();
+        storages.sort();
+        for storage in storages {
+            let dir = web_profiles.join(&storage);
+            if !dir.is_dir() {
+                continue;
+            }
+            // A profile being written to would be archived half updated.
+            if backup::in_use(&dir, SystemTime::now()) {
+                return Err(format!(
+                    "Account {} is in use, close WhatsApp-QT before exporting accounts",
+                    storage
+                ));
+            }
+            println!("Adding account {}", storage);
+            archive
+                .append_dir_all(Path::new(ACCOUNTS_ENTRY).join(&storage), &dir)
+                .map_err(io_error)?;
+            manifest.accounts.push(storage);
+        }
+    }
+    let json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    append_bytes(&mut archive, Path::new(MANIFEST_FILE), &json).map_err(io_error)?;
+
+    let gzip = archive.into_inner().map_err(io_error)?;
+    let sealed = gzip.finish().map_err(io_error)?;
+    let out = sealed.finish().map_err(io_error)?;
+    out.into_inner()
+        .map_err(|e| io_error(e.into_error()))?
+        .sync_all()
+        .map_err(io_error)?;
+    println!(
+        "Exported {} profile(s) and {} account(s) to {}",
+        manifest.profiles.len(),
+        manifest.accounts.len(),
+        file.display()
+    );
+    Ok(())
+}
+
+fn append_bytes<W: Write>(
+    archive: &mut tar::Builder<W>,
+    path: &Path,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(Local::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, path, data)
+}
+
+fn import(file: &Path, mode: ImportMode) -> Result<(), String> {
+    let cfg = paths::config_dir().ok_or("Could not find config directory")?;
+    let data = paths::data_dir().ok_or("Could not find data directory")?;
+    let web_profiles = paths::profiles_dir().ok_or("Could not find data directory")?;
+
+    let io_error = |e: io::Error| format!("Failed to read {}: {}", file.display(), e);
+    let mut input = BufReader::new(File::open(file).map_err(io_error)?);
+    let mut header = [0u8; MAGIC.len() + SALT_LEN + STREAM_NONCE_LEN];
+    input.read_exact(&mut header).map_err(io_error)?;
+    let (magic, rest) = header.split_at(MAGIC.len());
+    let (salt, nonce) = rest.split_at(SALT_LEN);
+    if magic != MAGIC {
+        return Err(format!("{} is not a WhatsApp-QT bundle", file.display()));
+    }
+    let passphrase = secrets::prompt_passphrase("Bundle passphrase: ", false)?;
+    let key = secrets::derive_key(&passphrase, salt)?;
+    let decryptor = DecryptorBE32::from_aead(
+        ChaCha20Poly1305::new(Key::from_slice(key.expose_secret())),
+        GenericArray::from_slice(nonce),
+    );
+    let opened = OpeningReader::new(input, decryptor).map_err(io_error)?;
+
+    // Unpacked next to the web profiles so accounts can be renamed into
+    // place instead of copied.
+    let staging = data.join(format!(
+        "bundle-import-{}",
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    let result = tar::Archive::new(GzDecoder::new(opened))
+        .unpack(&staging)
+        .map_err(io_error)
+        .and_then(|()| apply(&staging, &cfg, &web_profiles, mode));
+    if let Err(e) = fs::remove_dir_all(&staging) {
+        println!("Failed to remove {}: {}", staging.display(), e);
+    }
+    result
+}
+
+/// Plans every profile first and writes only when all of them are valid.
+fn apply(staging: &Path, cfg: &Path, web_profiles: &Path, mode: ImportMode) -> Result<(), String> {
+    let manifest: Manifest = fs::read_to_string(staging.join(MANIFEST_FILE))
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .map_err(|e| format!("The bundle has no valid manifest: {}", e))?;
+    if manifest.format > FORMAT_VERSION {
+        return Err("The bundle was written by a newer version".to_string());
+    }
+    println!("Importing bundle from {}", manifest.created_at);
+    // Account names become directory names below the web profiles.
+    for storage in &manifest.accounts {
+        if !profiles::is_valid_name(storage) {
+            return Err(format!("Invalid account name {:?} in the bundle", storage));
+        }
+        if !staging.join(ACCOUNTS_ENTRY).join(storage).is_dir() {
+            return Err(format!("Account {} is missing from the bundle", storage));
+        }
+    }
+
+    let mut local_profiles = HashMap::new();
+    let mut used_storages = HashSet::new();
+    for name in profiles::list() {
+        let Some(dir) = profiles::dir(&name) else {
+            continue;
+        };
+        let mut local = settings::load(&dir)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_default();
+        local.decrypt_fields().map_err(|e| e.to_string())?;
+        used_storages.extend(local.tabs.iter().map(|tab| tab.storage.clone()));
+        local_profiles.insert(name, local);
+    }
+
+    // Accounts keep their directory unless merging would clash with one
+    // that belongs to a different local account.
+    let mut renamed = HashMap::new();
+    for storage in &manifest.accounts {
+        let clashes = used_storages.contains(storage) || web_profiles.join(storage).exists();
+        if mode == ImportMode::Merge && clashes {
+            let free = (0..)
+                .map(settings::tab_storage)
+                .find(|candidate| {
+                    !used_storages.contains(candidate)
+                        && !web_profiles.join(candidate).exists()
+                        && !manifest.accounts.contains(candidate)
+                })
+                .expect("storage names are unbounded");
+            used_storages.insert(free.clone());
+            renamed.insert(storage.clone(), free);
+        }
+    }
+    for storage in &manifest.accounts {
+        let target = web_profiles.join(renamed.get(storage).unwrap_or(storage));
+        if target.is_dir() && backup::in_use(&target, SystemTime::now()) {
+            return Err(format!(
+                "Account {} is in use, close WhatsApp-QT before replacing it",
+                storage
+            ));
+        }
+    }
+
+    let mut planned = Vec::new();
+    for name in &manifest.profiles {
+        if !profiles::is_valid_name(name) {
+            return Err(format!("Invalid profile name {:?} in the bundle", name));
+        }
+        let relative = if name == profiles::DEFAULT_PROFILE {
+            PathBuf::new()
+        } else {
+            Path::new("profiles").join(name)
+        };
+        let path = staging
+            .join(CONFIG_ENTRY)
+            .join(relative)
+            .join(SETTINGS_FILE);
+        let json = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut imported =
+            settings::parse(&json).map_err(|e| format!("Profile {}: {}", name, e))?;
+        for tab in &mut imported.tabs {
+            if let Some(storage) = renamed.get(&tab.storage) {
+                tab.storage = storage.clone();
+            }
+        }
+
+        let local = local_profiles.remove(name);
+        let mut result = match (mode, local) {
+            (ImportMode::Merge, Some(local)) => merge(local, imported),
+            (_, local) => {
+                // Paths from the old machine rarely exist on the new one.
+                if validation::check_download_path(&imported.download_path).is_err() {
+                    imported.download_path = local
+                        .as_ref()
+                        .map(|local| local.download_path.clone())
+                        .unwrap_or_else(settings::default_download_path);
+                }
+                imported.encryption = local.and_then(|local| local.encryption);
+                imported
+            }
+        };
+        result.schema_version = settings::SCHEMA_VERSION;
+        // Themes from the bundle are only copied once everything checks out.
+        let bundled_theme = staging
+            .join(CONFIG_ENTRY)
+            .join(css::THEMES_DIR)
+            .join(&result.theme)
+            .with_extension(css::THEME_EXTENSION);
+        let errors: Vec<_> = validation::validate(&result)
+            .into_iter()
+            .filter(|error| !(error.field == "theme" && bundled_theme.exists()))
+            .collect();
+        if !errors.is_empty() {
+            let lines: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            return Err(format!(
+                "Profile {} would be invalid:\n  {}",
+                name,
+                lines.join("\n  ")
+            ));
+        }
+        let dir = profiles::dir(name).ok_or("Could not find config directory")?;
+        planned.push((dir, result));
+    }
+
+    save_all(planned)?;
+    for extra in EXTRA_CONFIG_DIRS {
+        copy_files(
+            &staging.join(CONFIG_ENTRY).join(extra),
+            &cfg.join(extra),
+            mode == ImportMode::Replace,
+        )
+        .map_err(|e| format!("Failed to import {}: {}", extra, e))?;
+    }
+    for storage in &manifest.accounts {
+        let target = web_profiles.join(renamed.get(storage).unwrap_or(storage));
+        if target.exists() {
+            fs::remove_dir_all(&target)
+                .map_err(|e| format!("Failed to replace {}: {}", target.display(), e))?;
+        }
+        paths::move_path(&staging.join(ACCOUNTS_ENTRY).join(storage), &target)
+            .map_err(|e| format!("Failed to import account {}: {}", storage, e))?;
+        println!("Imported account {} as {}", storage, target.display());
+    }
+    Ok(())
+}
+
+/// Keeps the local values and adds the bundle's tabs, rules and push
+/// targets that aren't there yet.
+fn merge(mut local: AppSettings, imported: AppSettings) -> AppSettings {
+    for tab in imported.tabs {
+        if !local.tabs.iter().any(|known| known.storage == tab.storage) {
+            local.tabs.push(tab);
+        }
+    }
+    let notifications = &mut local.notification_settings;
+    for rule in imported.notification_settings.rules {
+        if !notifications
+            .rules
+            .iter()
+            .any(|known| known.account == rule.account && known.sender == rule.sender)
+        {
+            notifications.rules.push(rule);
+        }
+    }
+    for target in imported.notification_settings.push_targets {
+        if !notifications
+            .push_targets
+            .iter()
+            .any(|known| known.name == target.name)
+        {
+            notifications.push_targets.push(target);
+        }
+    }
+    local
+}
+
+fn save_all(planned: Vec<(PathBuf, AppSettings)>) -> Result<(), String> {
+    let writer = SettingsWriter::start();
+    let mut results = Vec::new();
+    for (dir, settings) in planned {
+        let (done, result) = mpsc::channel();
+        writer.save(
+            dir.clone(),
+            &settings,
+            Box::new(move |outcome| {
+                let _ = done.send(outcome);
+            }),
+        );
+        results.push((dir, result));
+    }
+    writer.flush();
+    for (dir, result) in results {
+        result
+            .recv()
+            .map_err(|_| "Settings were not written".to_string())?
+            .map_err(|e| format!("{}: {}", dir.display(), e))?;
+        println!("Imported settings into {}", dir.display());
+    }
+    Ok(())
+}
+
+/// Copies the files below `from`, keeping existing ones unless `overwrite`.
+fn copy_files(from: &Path, to: &Path, overwrite: bool) -> io::Result<()> {
+    if !from.is_dir() {
+        return Ok(());
+    }
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_files(&entry.path(), &target, overwrite)?;
+        } else if overwrite || !target.exists() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Encrypts everything written to it in `CHUNK_LEN` pieces.
+struct SealingWriter<W: Write> {
+    inner: W,
+    encryptor: Option<EncryptorBE32<ChaCha20Poly1305>>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> SealingWriter<W> {
+    /// Seals the last chunk, which may be empty, and returns the output.
+    fn finish(mut self) -> io::Result<W> {
+        let encryptor = self.encryptor.take().ok_or_else(sealing_error)?;
+        let sealed = encryptor
+            .encrypt_last(self.buffer.as_slice())
+            .map_err(|_| sealing_error())?;
+        self.inner.write_all(&sealed)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for SealingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        // A full chunk stays buffered, it may turn out to be the last one.
+        while self.buffer.len() > CHUNK_LEN {
+            let rest = self.buffer.split_off(CHUNK_LEN);
+            let encryptor = self.encryptor.as_mut().ok_or_else(sealing_error)?;
+            let sealed = encryptor
+                .encrypt_next(self.buffer.as_slice())
+                .map_err(|_| sealing_error())?;
+            self.inner.write_all(&sealed)?;
+            self.buffer = rest;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn sealing_error() -> io::Error {
+    io::Error::other("Failed to encrypt the bundle")
+}
+
+/// Decrypts what `SealingWriter` wrote, reading one chunk ahead to find the
+/// last one.
+struct OpeningReader<R: Read> {
+    inner: R,
+    decryptor: Option<DecryptorBE32<ChaCha20Poly1305>>,
+    sealed: Vec<u8>,
+    plain: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> OpeningReader<R> {
+    fn new(mut inner: R, decryptor: DecryptorBE32<ChaCha20Poly1305>) -> io::Result<Self> {
+        let sealed = read_chunk(&mut inner)?;
+        Ok(Self {
+            inner,
+            decryptor: Some(decryptor),
+            sealed,
+            plain: Vec::new(),
+            position: 0,
+        })
+    }
+
+    fn open_next(&mut self) -> io::Result<()> {
+        let next = read_chunk(&mut self.inner)?;
+        let sealed = std::mem::replace(&mut self.sealed, next);
+        self.plain = if self.sealed.is_empty() {
+            let decryptor = self.decryptor.take().ok_or_else(opening_error)?;
+            decryptor.decrypt_last(sealed.as_slice())
+        } else {
+            let decryptor = self.decryptor.as_mut().ok_or_else(opening_error)?;
+            decryptor.decrypt_next(sealed.as_slice())
+        }
+        .map_err(|_| opening_error())?;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for OpeningReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plain.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+            self.open_next()?;
+        }
+        let count = buf.len().min(self.plain.len() - self.position);
+        buf[..count].copy_from_slice(&self.plain[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+fn read_chunk<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(CHUNK_LEN + TAG_LEN);
+    reader
+        .take((CHUNK_LEN + TAG_LEN) as u64)
+        .read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+fn opening_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Wrong passphrase or damaged bundle",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+    const NONCE: [u8; STREAM_NONCE_LEN] = [1; STREAM_NONCE_LEN];
+
+    fn seal(data: &[u8]) -> Vec<u8> {
+        let mut sealed = SealingWriter {
+            inner: Vec::new(),
+            encryptor: Some(EncryptorBE32::from_aead(
+                ChaCha20Poly1305::new(Key::from_slice(&KEY)),
+                GenericArray::from_slice(&NONCE),
+            )),
+            buffer: Vec::new(),
+        };
+        // Writes that don't line up with the chunks.
+        for piece in data.chunks(1000) {
+            sealed.write_all(piece).unwrap();
+        }
+        sealed.finish().unwrap()
+    }
+
+    fn open(sealed: &[u8], key: &[u8; 32]) -> io::Result<Vec<u8>> {
+        let decryptor = DecryptorBE32::from_aead(
+            ChaCha20Poly1305::new(Key::from_slice(key)),
+            GenericArray::from_slice(&NONCE),
+        );
+        let mut plain = Vec::new();
+        OpeningReader::new(sealed, decryptor)?.read_to_end(&mut plain)?;
+        Ok(plain)
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn round_trips_across_chunk_boundaries() {
+        for len in [0, 1, CHUNK_LEN - 1, CHUNK_LEN, CHUNK_LEN + 1, 3 * CHUNK_LEN] {
+            let data = data(len);
+            assert_eq!(open(&seal(&data), &KEY).unwrap(), data, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn a_full_last_chunk_is_sealed_as_the_last() {
+        let sealed = seal(&data(2 * CHUNK_LEN));
+        assert_eq!(sealed.len(), 2 * (CHUNK_LEN + TAG_LEN));
+        assert_eq!(seal(&[]).len(), TAG_LEN);
+    }
+
+    #[test]
+    fn rejects_a_truncated_bundle_or_a_wrong_key() {
+        let sealed = seal(&data(3 * CHUNK_LEN));
+
+        assert!(open(&sealed[..2 * (CHUNK_LEN + TAG_LEN)], &KEY).is_err());
+        assert!(open(&sealed, &[8; 32]).is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use chrono::{Local, TimeZone};
+use qmetaobject::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::json_writer::JsonWriter;
+use crate::notification::{CallKind, IncomingCall};
+use crate::tray;
+
+pub const CALL_LOG_FILE: &str = "call_log.json";
+const CALL_LOG_LIMIT: usize = 500;
+/// Calls the page never reported as ended are logged as missed after this.
+const STALE_CALL_SECS: i64 = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallOutcome {
+    Missed,
+    Answered,
+    Declined,
+}
+
+impl CallOutcome {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CallOutcome::Missed => "missed",
+            CallOutcome::Answered => "answered",
+            CallOutcome::Declined => "declined",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallRecord {
+    pub id: u64,
+    /// Unix timestamp in seconds of when the call started ringing.
+    pub timestamp: i64,
+    /// Tab position when the call came in, see `storage`.
+    pub tab_index: i32,
+    /// Web profile of the account, which still finds it after tabs moved.
+    /// Empty in records from before it was stored.
+    #[serde(default)]
+    pub storage: String,
+    pub account: String,
+    pub caller: String,
+    pub kind: CallKind,
+    pub outcome: CallOutcome,
+    /// Missed calls count towards the tray and title until seen.
+    #[serde(default)]
+    pub seen: bool,
+}
+
+impl CallRecord {
+    pub fn time_label(&self) -> String {
+        Local
+            .timestamp_opt(self.timestamp, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// A call that is still ringing, with what the user did about it so far.
+struct ActiveCall {
+    call: IncomingCall,
+    storage: String,
+    started: i64,
+    outcome: Option<CallOutcome>,
+}
+
+/// Calls of all accounts persisted as JSON in the data directory,
+/// newest first.
+pub struct CallLog {
+    records: VecDeque<CallRecord>,
+    active: HashMap<i32, ActiveCall>,
+    next_id: u64,
+    writer: Option<JsonWriter<VecDeque<CallRecord>>>,
+}
+
+impl Default for CallLog {
+    fn default() -> Self {
+        Self {
+            records: VecDeque::new(),
+            active: HashMap::new(),
+            next_id: 1,
+            writer: None,
+        }
+    }
+}
+
+impl CallLog {
+    pub fn load(&mut self, path: PathBuf) {
+        self.records = match fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                println!("Ignoring unreadable call log: {}", e);
+                VecDeque::new()
+            }),
+            Err(_) => VecDeque::new(),
+        };
+        self.records.truncate(CALL_LOG_LIMIT);
+        self.next_id = self.records.iter().map(|r| r.id).max().unwrap_or(0) + 1;
+        self.writer = Some(JsonWriter::start(path, "call log"));
+    }
+
+    pub fn get(&self, row: usize) -> Option<&CallRecord> {
+        self.records.get(row)
+    }
+
+    pub fn get_mut(&mut self, row: usize) -> Option<&mut CallRecord> {
+        self.records.get_mut(row)
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn missed_count(&self) -> usize {
+        self.records
+            .iter()
+            .filter(|r| r.outcome == CallOutcome::Missed && !r.seen)
+            .count()
+    }
+
+    /// Starts tracking a ringing call on the account `storage`, returns
+    /// false if the tab already rings.
+    pub fn start(&mut self, call: IncomingCall, storage: String) -> bool {
+        if self.active.contains_key(&call.tab_index) {
+            return false;
+        }
+        self.active.insert(
+            call.tab_index,
+            ActiveCall {
+                call,
+                storage,
+                started: Local::now().timestamp(),
+                outcome: None,
+            },
+        );
+        true
+    }
+
+    /// Remembers how the user reacted while the call is still ringing.
+    pub fn set_outcome(&mut self, tab_index: i32, outcome: CallOutcome) {
+        if let Some(active) = self.active.get_mut(&tab_index) {
+            active.outcome = Some(outcome);
+        }
+    }
+
+    /// Logs the call on `tab_index` once it stopped ringing. Calls nobody
+    /// answered or declined are missed.
+    pub fn finish(&mut self, tab_index: i32, outcome: Option<CallOutcome>) -> bool {
+        let Some(active) = self.active.remove(&tab_index) else {
+            return false;
+        };
+        let outcome = outcome.or(active.outcome).unwrap_or(CallOutcome::Missed);
+        self.records.push_front(CallRecord {
+            id: self.next_id,
+            timestamp: active.started,
+            tab_index,
+            storage: active.storage,
+            account: active.call.account,
+            caller: active.call.caller,
+            kind: active.call.kind,
+            outcome,
+            seen: outcome != CallOutcome::Missed,
+        });
+        self.next_id += 1;
+        self.records.truncate(CALL_LOG_LIMIT);
+        true
+    }
+
+    /// Logs calls the page never reported as ended, returning their tabs.
+    pub fn finish_stale(&mut self) -> Vec<i32> {
+        let cutoff = Local::now().timestamp() - STALE_CALL_SECS;
+        let stale: Vec<i32> = self
+            .active
+            .iter()
+            .filter(|(_, active)| active.started < cutoff)
+            .map(|(tab_index, _)| *tab_index)
+            .collect();
+        for &tab_index in &stale {
+            self.finish(tab_index, None);
+        }
+        stale
+    }
+
+    pub fn mark_all_seen(&mut self) {
+        self.records.iter_mut().for_each(|r| r.seen = true);
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Queues the records for writing on the background task.
+    pub fn save(&self) {
+        if let Some(writer) = &self.writer {
+            writer.save(self.records.clone());
+        }
+    }
+}
+
+const ROLE_ACCOUNT: i32 = USER_ROLE;
+const ROLE_CALLER: i32 = USER_ROLE + 1;
+const ROLE_KIND: i32 = USER_ROLE + 2;
+const ROLE_OUTCOME: i32 = USER_ROLE + 3;
+const ROLE_TIME: i32 = USER_ROLE + 4;
+const ROLE_SEEN: i32 = USER_ROLE + 5;
+const ROLE_TAB_INDEX: i32 = USER_ROLE + 6;
+
+/// List model over the call log of all accounts.
+#[derive(QObject, Default)]
+pub struct CallLogModel {
+    base: qt_base_class!(trait QAbstractListModel),
+    missed_count: qt_property!(i32; NOTIFY missed_count_changed),
+    missed_count_changed: qt_signal!(),
+    log: CallLog,
+
+    mark_all_seen: qt_method!(
+        fn mark_all_seen(&mut self) {
+            (self as &mut dyn QAbstractListModel).begin_reset_model();
+            self.log.mark_all_seen();
+            (self as &mut dyn QAbstractListModel).end_reset_model();
+            self.log_changed();
+        }
+    ),
+    clear: qt_method!(
+        fn clear(&mut self) {
+            (self as &mut dyn QAbstractListModel).begin_reset_model();
+            self.log.clear();
+            (self as &mut dyn QAbstractListModel).end_reset_model();
+            self.log_changed();
+        }
+    ),
+}
+
+impl CallLogModel {
+    pub fn load(&mut self, path: PathBuf) {
+        (self as &mut dyn QAbstractListModel).begin_reset_model();
+        self.log.load(path);
+        (self as &mut dyn QAbstractListModel).end_reset_model();
+        self.update_missed_count();
+    }
+
+    pub fn record(&self, row: i32) -> Option<&CallRecord> {
+        self.log.get(row as usize)
+    }
+
+    pub fn start(&mut self, call: IncomingCall, storage: String) -> bool {
+        self.log.start(call, storage)
+    }
+
+    pub fn set_outcome(&mut self, tab_index: i32, outcome: CallOutcome) {
+        self.log.set_outcome(tab_index, outcome);
+    }
+
+    pub fn finish(&mut self, tab_index: i32, outcome: Option<CallOutcome>) {
+        (self as &mut dyn QAbstractListModel).begin_reset_model();
+        let changed = self.log.finish(tab_index, outcome);
+        (self as &mut dyn QAbstractListModel).end_reset_model();
+        if changed {
+            self.log_changed();
+        }
+    }
+
+    pub fn finish_stale(&mut self) -> Vec<i32> {
+        (self as &mut dyn QAbstractListModel).begin_reset_model();
+        let stale = self.log.finish_stale();
+        (self as &mut dyn QAbstractListModel).end_reset_model();
+        if !stale.is_empty() {
+            self.log_changed();
+        }
+        stale
+    }
+
+    pub fn mark_seen(&mut self, row: i32) {
+        match self.log.get_mut(row as usize) {
+            Some(record) if !record.seen => record.seen = true,
+            _ => return,
+        }
+        let index = (self as &mut dyn QAbstractListModel).row_index(row);
+        (self as &mut dyn QAbstractListModel).data_changed(index.clone(), index);
+        self.log_changed();
+    }
+
+    fn log_changed(&mut self) {
+        self.log.save();
+        self.update_missed_count();
+    }
+
+    fn update_missed_count(&mut self) {
+        let missed = self.log.missed_count() as i32;
+        if missed != self.missed_count {
+            self.missed_count = missed;
+            self.missed_count_changed();
+            tray::set_missed_calls(missed as u32);
+        }
+    }
+}
+
+impl QAbstractListModel for CallLogModel {
+    fn row_count(&self) -> i32 {
+        self.log.len() as i32
+    }
+
+    fn data(&self, index: QModelIndex, role: i32) -> QVariant {
+        let Some(record) = self.log.get(index.row() as usize) else {
+            return QVariant::default();
+        };
+        match role {
+            ROLE_ACCOUNT => QString::from(record.account.as_str()).into(),
+            ROLE_CALLER => QString::from(record.caller.as_str()).into(),
+            ROLE_KIND => QString::from(record.kind.label()).into(),
+            ROLE_OUTCOME => QString::from(record.outcome.label()).into(),
+            ROLE_TIME => QString::from(record.time_label()).into(),
+            ROLE_SEEN => record.seen.into(),
+            ROLE_TAB_INDEX => record.tab_index.into(),
+            _ => QVariant::default(),
+        }
+    }
+
+    fn role_names(&self) -> HashMap<i32, QByteArray> {
+        HashMap::from([
+            (ROLE_ACCOUNT, "account".into()),
+            (ROLE_CALLER, "caller".into()),
+            (ROLE_KIND, "kind".into()),
+            (ROLE_OUTCOME, "outcome".into()),
+            (ROLE_TIME, "time".into()),
+            (ROLE_SEEN, "seen".into()),
+            (ROLE_TAB_INDEX, "tabIndex".into()),
+        ])
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+use crate::css;
+
+#[cfg(target_os = "linux")]
+const PORTAL_NAME: &str = "org.freedesktop.portal.Desktop";
+#[cfg(target_os = "linux")]
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+#[cfg(target_os = "linux")]
+const SETTINGS_INTERFACE: &str = "org.freedesktop.portal.Settings";
+#[cfg(target_os = "linux")]
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+#[cfg(target_os = "linux")]
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+
+/// The desktop's `org.freedesktop.appearance color-scheme` preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorScheme {
+    #[default]
+    NoPreference,
+    Dark,
+    Light,
+}
+
+impl ColorScheme {
+    fn from_portal(value: u32) -> Self {
+        match value {
+            1 => ColorScheme::Dark,
+            2 => ColorScheme::Light,
+            _ => ColorScheme::NoPreference,
+        }
+    }
+
+    /// Bundled theme to use for `system`, none keeps WhatsApp's own look.
+    pub fn variant(self) -> Option<css::Variant> {
+        match self {
+            ColorScheme::NoPreference => None,
+            ColorScheme::Dark => Some(css::Variant::Dark),
+            ColorScheme::Light => Some(css::Variant::Light),
+        }
+    }
+}
+
+/// Asks the XDG settings portal, `ReadOne` first and the older `Read` on
+/// portals that don't have it.
+#[cfg(target_os = "linux")]
+pub fn read() -> Result<ColorScheme, dbus::Error> {
+    use dbus::arg::Variant;
+
+    let conn = dbus::blocking::Connection::new_session()?;
+    let proxy = conn.with_proxy(PORTAL_NAME, PORTAL_PATH, Duration::from_millis(2000));
+    let args = (APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY);
+    let value =
+        match proxy.method_call::<(Variant<u32>,), _, _, _>(SETTINGS_INTERFACE, "ReadOne", args) {
+            Ok((Variant(value),)) => value,
+            Err(_) => {
+                let (Variant(Variant(value)),): (Variant<Variant<u32>>,) =
+                    proxy.method_call(SETTINGS_INTERFACE, "Read", args)?;
+                value
+            }
+        };
+    Ok(ColorScheme::from_portal(value))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn read() -> Result<ColorScheme, String> {
+    Ok(ColorScheme::NoPreference)
+}
+
+/// Calls `on_change` whenever the portal reports a new colour scheme, e.g.
+/// when the desktop switches to dark mode in the evening.
+#[cfg(target_os = "linux")]
+pub fn watch<F>(on_change: F)
+where
+    F: Fn(ColorScheme) + Send + 'static,
+{
+    use dbus::arg::Variant;
+    use dbus::message::MatchRule;
+
+    std::thread::spawn(move || {
+        let conn = match dbus::blocking::Connection::new_session() {
+            Ok(conn) => conn,
+            Err(e) => {
+                println!("Cannot watch the desktop colour scheme: {}", e);
+                return;
+            }
+        };
+
+        let rule =
+            MatchRule::new_signal(SETTINGS_INTERFACE, "SettingChanged").with_path(PORTAL_PATH);
+        let added = conn.add_match(
+            rule,
+            move |(namespace, key, value): (String, String, Variant<u32>), _, _| {
+                if namespace == APPEARANCE_NAMESPACE && key == COLOR_SCHEME_KEY {
+                    on_change(ColorScheme::from_portal(value.0));
+                }
+                true
+            },
+        );
+        if let Err(e) = added {
+            println!("Cannot watch the desktop colour scheme: {}", e);
+            return;
+        }
+
+        loop {
+            if let Err(e) = conn.process(Duration::from_millis(1000)) {
+                println!("Colour scheme watch stopped: {}", e);
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch<F>(_on_change: F)
+where
+    F: Fn(ColorScheme) + Send + 'static,
+{
+}
@@ -0,0 +1,169 @@
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use crate::policy;
+use crate::profiles;
+use crate::settings::{self, AppSettings};
+use crate::settings_writer::SettingsWriter;
+use crate::validation;
+
+const USAGE: &str = "Usage: whatsapp-qt [--profile <name>] config <command>
+
+Commands:
+  get <key>          Print one setting, e.g. notification_settings.enabled
+  set <key> <value>  Change one setting; the value is JSON or a plain string
+  list               Print every setting as key = value
+  export [file]      Write all settings as JSON to the file or stdout
+  import <file|->    Replace all settings with a JSON file or stdin";
+
+/// Runs `whatsapp-qt config ...` without starting Qt. `args` are the words
+/// after `config`.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let profile = profiles::startup_profile();
+    let dir = profiles::dir(&profile).ok_or("Could not find config directory")?;
+
+    match args.as_slice() {
+        ["get", key] => {
+            let values = to_values(&load(&dir)?)?;
+            let value = settings::get_key(&values, key)
+                .ok_or_else(|| format!("Unknown setting {}", key))?;
+            match value {
+                Value::String(text) => println!("{}", text),
+                other => println!("{}", pretty(other)?),
+            }
+            Ok(())
+        }
+        ["set", key, value] => {
+            if let Some(reason) = policy::get().lock_reason(key) {
+                return Err(format!("{} is locked: {}", key, reason));
+            }
+            let current = load(&dir)?;
+            let mut values = to_values(&current)?;
+            if settings::get_key(&values, key).is_none() {
+                return Err(format!("Unknown setting {}", key));
+            }
+            // `dark` and `"dark"` both mean the string.
+            let value =
+                serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+            settings::set_key(&mut values, key, value, true);
+            let mut updated: AppSettings = serde_json::from_value(Value::Object(values))
+                .map_err(|e| format!("Invalid value for {}: {}", key, e))?;
+            updated.encryption = current.encryption;
+            save(dir, updated)
+        }
+        ["list"] => {
+            let values = to_values(&load(&dir)?)?;
+            let mut lines = Vec::new();
+            flatten("", &Value::Object(values), &mut lines);
+            for line in lines {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        ["export"] | ["export", _] => {
+            let json = pretty(&Value::Object(to_values(&load(&dir)?)?))?;
+            match args.get(1) {
+                Some(file) => fs::write(file, json + "\n")
+                    .map_err(|e| format!("Failed to write {}: {}", file, e)),
+                None => {
+                    println!("{}", json);
+                    Ok(())
+                }
+            }
+        }
+        ["import", source] => {
+            let data = if *source == "-" {
+                let mut data = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut data)
+                    .map_err(|e| format!("Failed to read stdin: {}", e))?;
+                data
+            } else {
+                fs::read_to_string(source)
+                    .map_err(|e| format!("Failed to read {}: {}", source, e))?
+            };
+            let current = load(&dir)?;
+            let mut imported = settings::parse(&data)?;
+            imported
+                .decrypt_fields()
+                .map_err(|e| format!("Can't read encrypted values in {}: {}", source, e))?;
+            imported.encryption = current.encryption;
+            save(dir, imported)
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// The profile's settings, decrypted, or the defaults when there are none.
+fn load(dir: &Path) -> Result<AppSettings, String> {
+    let mut loaded = settings::load(dir)
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    loaded.decrypt_fields().map_err(|e| e.to_string())?;
+    Ok(loaded)
+}
+
+/// Settings as exported: plain text values, without the key header.
+fn to_values(settings: &AppSettings) -> Result<Map<String, Value>, String> {
+    let mut plain = settings.clone();
+    plain.encryption = None;
+    match serde_json::to_value(plain).map_err(|e| e.to_string())? {
+        Value::Object(values) => Ok(values),
+        _ => Err("Settings are not a JSON object".to_string()),
+    }
+}
+
+fn pretty(value: &Value) -> Result<String, String> {
+    serde_json::to_string_pretty(value).map_err(|e| e.to_string())
+}
+
+fn flatten(prefix: &str, value: &Value, lines: &mut Vec<String>) {
+    let child = |key: &str| {
+        if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", prefix, key)
+        }
+    };
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, value) in object {
+                flatten(&child(key), value, lines);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (index, item) in items.iter().enumerate() {
+                flatten(&format!("{}[{}]", prefix, index), item, lines);
+            }
+        }
+        other => lines.push(format!("{} = {}", prefix, other)),
+    }
+}
+
+/// Validates and writes `settings` the way the app does, the writer encrypts.
+fn save(dir: PathBuf, mut settings: AppSettings) -> Result<(), String> {
+    let errors = validation::validate(&settings);
+    if !errors.is_empty() {
+        let lines: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        return Err(format!("Invalid settings:\n  {}", lines.join("\n  ")));
+    }
+    settings.schema_version = settings::SCHEMA_VERSION;
+
+    let (done, result) = mpsc::channel();
+    let writer = SettingsWriter::start();
+    writer.save(
+        dir,
+        &settings,
+        Box::new(move |outcome| {
+            let _ = done.send(outcome);
+        }),
+    );
+    writer.flush();
+    result
+        .recv()
+        .map_err(|_| "Settings were not written".to_string())?
+}
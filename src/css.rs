@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::paths;
+use crate::profiles;
+
+/// Theme following the desktop's light or dark preference, see `color_scheme`.
+pub const SYSTEM_THEME: &str = "system";
+/// User themes live in `<config>/themes/<name>.json`.
+pub const THEMES_DIR: &str = "themes";
+pub const THEME_EXTENSION: &str = "json";
+/// Longest `extends` chain, so a loop between user themes can't recurse forever.
+const MAX_EXTENDS: usize = 8;
+
+/// Named after their variant, which is how `system` picks one.
+const BUNDLED: &[(&str, &str)] = &[
+    ("light", include_str!("../resources/themes/light.json")),
+    ("dark", include_str!("../resources/themes/dark.json")),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    Light,
+    Dark,
+}
+
+impl Variant {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Variant::Light => "light",
+            Variant::Dark => "dark",
+        }
+    }
+}
+
+/// How a service's pages are themed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeStrategy {
+    /// Switch the page's own light or dark mode and add only the rules of
+    /// user themes, bundled rules target class names that change.
+    #[default]
+    Native,
+    /// Restyle the page with every rule of the theme.
+    Css,
+}
+
+impl ThemeStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThemeStrategy::Native => "native",
+            ThemeStrategy::Css => "css",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(ThemeStrategy::Native),
+            "css" => Some(ThemeStrategy::Css),
+            _ => None,
+        }
+    }
+}
+
+/// A theme definition file. `palette` and `fonts` define the variables the
+/// rules use as `$name`.
+#[derive(Debug, Default, Deserialize)]
+struct ThemeFile {
+    /// Theme whose palette, fonts and rules this one starts from.
+    #[serde(default)]
+    extends: Option<String>,
+    #[serde(default)]
+    variant: Option<Variant>,
+    #[serde(default)]
+    palette: BTreeMap<String, String>,
+    #[serde(default)]
+    fonts: BTreeMap<String, String>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Rule {
+    selector: String,
+    #[serde(default)]
+    declarations: BTreeMap<String, String>,
+    #[serde(skip)]
+    bundled: bool,
+}
+
+/// A theme rendered for the web views and the window chrome.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub variant: Variant,
+    /// Background of the main window, the palette's `window` or `background`.
+    pub window_color: Option<String>,
+    /// Every rule, for `ThemeStrategy::Css`.
+    pub css: String,
+    /// Only the rules from user theme files, for `ThemeStrategy::Native`.
+    pub extra_css: String,
+}
+
+impl Theme {
+    pub fn css_for(&self, strategy: ThemeStrategy) -> &str {
+        match strategy {
+            ThemeStrategy::Native => &self.extra_css,
+            ThemeStrategy::Css => &self.css,
+        }
+    }
+}
+
+/// `system`, the bundled themes and the user's, in that order.
+pub fn available() -> Vec<String> {
+    let mut user: Vec<String> = user_dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == THEME_EXTENSION))
+        .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+        .filter(|name| is_user_theme_name(name))
+        .collect();
+    user.sort();
+
+    let mut names = vec![SYSTEM_THEME.to_string()];
+    names.extend(BUNDLED.iter().map(|(name, _)| name.to_string()));
+    names.extend(user);
+    names
+}
+
+/// Reads and renders the bundled or user theme `name`.
+pub fn load(name: &str) -> Result<Theme, String> {
+    let file = read(name, 0)?;
+    if let Some(clash) = file
+        .palette
+        .keys()
+        .find(|key| file.fonts.contains_key(*key))
+    {
+        return Err(format!(
+            "Theme {} defines ${} both as a colour and as a font",
+            name, clash
+        ));
+    }
+    Ok(Theme {
+        variant: file.variant.unwrap_or(Variant::Light),
+        window_color: file
+            .palette
+            .get("window")
+            .or_else(|| file.palette.get("background"))
+            .cloned(),
+        css: render(name, &file, |_| true)?,
+        extra_css: render(name, &file, |rule| !rule.bundled)?,
+    })
+}
+
+fn user_dir() -> Option<PathBuf> {
+    paths::config_dir().map(|cfg| cfg.join(THEMES_DIR))
+}
+
+/// Bundled names can't be replaced, a user theme can `extend` them instead.
+fn is_user_theme_name(name: &str) -> bool {
+    profiles::is_valid_name(name)
+        && name != SYSTEM_THEME
+        && !BUNDLED.iter().any(|(bundled, _)| *bundled == name)
+}
+
+fn bundled(name: &str) -> Option<&'static str> {
+    BUNDLED
+        .iter()
+        .find(|(bundled, _)| *bundled == name)
+        .map(|(_, data)| *data)
+}
+
+fn source(name: &str) -> Result<String, String> {
+    if let Some(data) = bundled(name) {
+        return Ok(data.to_string());
+    }
+    let path = user_dir()
+        .filter(|_| is_user_theme_name(name))
+        .map(|dir| dir.join(name).with_extension(THEME_EXTENSION));
+    match path {
+        Some(path) if path.exists() => fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e)),
+        _ => Err(format!(
+            "Unknown theme \"{}\", expected one of {}",
+            name,
+            available().join(", ")
+        )),
+    }
+}
+
+/// Parses `name` with the themes it extends merged in: their palette and
+/// fonts are overridden by the same names, their rules come first.
+fn read(name: &str, depth: usize) -> Result<ThemeFile, String> {
+    if depth > MAX_EXTENDS {
+        return Err(format!("Theme {} extends too many themes", name));
+    }
+    let mut file: ThemeFile = serde_json::from_str(&source(name)?)
+        .map_err(|e| format!("Invalid theme {}: {}", name, e))?;
+    if bundled(name).is_some() {
+        for rule in &mut file.rules {
+            rule.bundled = true;
+        }
+    }
+    let Some(parent) = file.extends.take() else {
+        return Ok(file);
+    };
+    let mut base = read(&parent, depth + 1)?;
+    base.variant = file.variant.or(base.variant);
+    base.palette.extend(file.palette);
+    base.fonts.extend(file.fonts);
+    base.rules.extend(file.rules);
+    Ok(base)
+}
+
+/// CSS for the rules `include` picks, empty when there are none.
+fn render(name: &str, file: &ThemeFile, include: impl Fn(&Rule) -> bool) -> Result<String, String> {
+    let lookup = |var: &str| file.palette.get(var).or_else(|| file.fonts.get(var));
+    let rules: Vec<&Rule> = file.rules.iter().filter(|rule| include(rule)).collect();
+    if rules.is_empty() {
+        return Ok(String::new());
+    }
+    let mut css = format!("/* {} theme */\n", name);
+    for rule in rules {
+        css.push_str(&format!("{} {{\n", rule.selector));
+        for (property, value) in &rule.declarations {
+            let value = substitute(value, lookup).map_err(|var| {
+                format!(
+                    "Unknown variable ${} in \"{}\" of theme {}",
+                    var, rule.selector, name
+                )
+            })?;
+            css.push_str(&format!("    {}: {} !important;\n", property, value));
+        }
+        css.push_str("}\n");
+    }
+    Ok(css)
+}
+
+/// Replaces each `$name` in `value`, failing with the first unknown name.
+fn substitute<'a>(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<&'a String>,
+) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(after.len());
+        let var = &after[..end];
+        out.push_str(lookup(var).ok_or_else(|| var.to_string())?);
+        rest = &after[end..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
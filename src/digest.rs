@@ -0,0 +1,208 @@
+use chrono::{DateTime, Duration, Local, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::notification::{NotificationEvent, NotificationEventKind};
+
+pub const DIGEST_FILE: &str = "missed-digest.md";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestOutput {
+    Notification,
+    Markdown,
+    Both,
+}
+
+impl DigestOutput {
+    pub fn notification(&self) -> bool {
+        matches!(self, DigestOutput::Notification | DigestOutput::Both)
+    }
+
+    pub fn markdown(&self) -> bool {
+        matches!(self, DigestOutput::Markdown | DigestOutput::Both)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Deliver every N minutes, 0 = only at `times`.
+    #[serde(default = "default_interval_minutes")]
+    pub interval_minutes: u32,
+    /// Fixed local delivery times, "HH:MM".
+    #[serde(default)]
+    pub times: Vec<String>,
+    /// Deliver as soon as the window is shown again.
+    #[serde(default = "default_true")]
+    pub deliver_on_return: bool,
+    #[serde(default = "default_output")]
+    pub output: DigestOutput,
+}
+
+fn default_interval_minutes() -> u32 {
+    60
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_output() -> DigestOutput {
+    DigestOutput::Both
+}
+
+impl Default for DigestSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_minutes: default_interval_minutes(),
+            times: Vec::new(),
+            deliver_on_return: true,
+            output: default_output(),
+        }
+    }
+}
+
+impl DigestSettings {
+    /// First scheduled delivery after `last`.
+    pub fn next_due(&self, last: DateTime<Local>) -> Option<DateTime<Local>> {
+        let interval = (self.interval_minutes > 0)
+            .then(|| last + Duration::minutes(i64::from(self.interval_minutes)));
+        let fixed = self
+            .times
+            .iter()
+            .filter_map(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+            .filter_map(|time| {
+                let today = Local
+                    .from_local_datetime(&last.date_naive().and_time(time))
+                    .earliest()?;
+                Some(if today > last {
+                    today
+                } else {
+                    today + Duration::days(1)
+                })
+            });
+        interval.into_iter().chain(fixed).min()
+    }
+}
+
+/// Messages and calls collected while the window is hidden or DND is on.
+pub struct Digest {
+    pending: Vec<NotificationEvent>,
+    last_delivery: DateTime<Local>,
+}
+
+impl Digest {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            last_delivery: Local::now(),
+        }
+    }
+
+    pub fn collect(&mut self, event: NotificationEvent) {
+        self.pending.push(event);
+    }
+
+    pub fn is_due(&self, settings: &DigestSettings, now: DateTime<Local>) -> bool {
+        settings
+            .next_due(self.last_delivery)
+            .is_some_and(|due| now >= due)
+    }
+
+    /// Takes the collected events, restarting the schedule.
+    pub fn take(&mut self) -> Option<DigestReport> {
+        self.last_delivery = Local::now();
+        if self.pending.is_empty() {
+            return None;
+        }
+        Some(DigestReport {
+            events: std::mem::take(&mut self.pending),
+            created: self.last_delivery,
+        })
+    }
+}
+
+pub struct DigestReport {
+    events: Vec<NotificationEvent>,
+    created: DateTime<Local>,
+}
+
+impl DigestReport {
+    /// Events grouped by account, then chat.
+    fn grouped(&self) -> BTreeMap<&str, BTreeMap<&str, Vec<&NotificationEvent>>> {
+        let mut groups: BTreeMap<&str, BTreeMap<&str, Vec<&NotificationEvent>>> = BTreeMap::new();
+        for event in &self.events {
+            groups
+                .entry(event.account.as_str())
+                .or_default()
+                .entry(event.chat.as_str())
+                .or_default()
+                .push(event);
+        }
+        groups
+    }
+
+    pub fn title(&self) -> String {
+        let calls = self
+            .events
+            .iter()
+            .filter(|e| e.event == NotificationEventKind::Call)
+            .count();
+        let messages = self.events.len() - calls;
+        match calls {
+            0 => format!("{} missed messages", messages),
+            _ => format!("{} missed messages, {} calls", messages, calls),
+        }
+    }
+
+    /// One line per account with the chats that were active.
+    pub fn summary(&self) -> String {
+        self.grouped()
+            .iter()
+            .map(|(account, chats)| {
+                let chats: Vec<String> = chats
+                    .iter()
+                    .map(|(chat, events)| format!("{} ({})", chat, events.len()))
+                    .collect();
+                format!("{}: {}", account, chats.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!(
+            "# Missed messages\n\n_{} — {}_\n",
+            self.created.format("%Y-%m-%d %H:%M"),
+            self.title()
+        );
+        for (account, chats) in self.grouped() {
+            markdown.push_str(&format!("\n## {}\n", account));
+            for (chat, events) in chats {
+                markdown.push_str(&format!("\n### {}\n\n", chat));
+                for event in events {
+                    let time = Local
+                        .timestamp_opt(event.timestamp, 0)
+                        .single()
+                        .map(|t| t.format("%H:%M").to_string())
+                        .unwrap_or_default();
+                    let text = match event.call_kind {
+                        Some(kind) => format!("missed {} call", kind.label()),
+                        None => event.text.replace('\n', " "),
+                    };
+                    markdown.push_str(&format!("- {} **{}**: {}\n", time, event.sender, text));
+                }
+            }
+        }
+        markdown
+    }
+
+    pub fn write_markdown(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, self.to_markdown())
+    }
+}
@@ -0,0 +1,119 @@
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc as std_mpsc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::runtime::runtime;
+
+/// Changes arriving within this window are written once.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+/// A steady stream of changes is still written this often.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+enum Command<T> {
+    Save(T),
+    Flush(std_mpsc::Sender<()>),
+}
+
+/// Writes a JSON data file such as the notification history from a
+/// background task, so a burst of changes costs one write and never blocks
+/// the GUI thread. Each write goes through a temp file and a rename, a crash
+/// keeps the previous file.
+pub struct JsonWriter<T> {
+    commands: mpsc::UnboundedSender<Command<T>>,
+}
+
+impl<T: Serialize + Send + 'static> JsonWriter<T> {
+    /// `what` names the data in log messages.
+    pub fn start(path: PathBuf, what: &'static str) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        runtime().spawn(run(path, what, receiver));
+        Self { commands }
+    }
+
+    pub fn save(&self, value: T) {
+        let _ = self.commands.send(Command::Save(value));
+    }
+}
+
+impl<T> JsonWriter<T> {
+    /// Writes pending changes now and waits for them to hit the disk.
+    pub fn flush(&self) {
+        let (done, wait) = std_mpsc::channel();
+        if self.commands.send(Command::Flush(done)).is_ok() {
+            let _ = wait.recv_timeout(FLUSH_TIMEOUT);
+        }
+    }
+}
+
+impl<T> Drop for JsonWriter<T> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+async fn run<T: Serialize>(
+    path: PathBuf,
+    what: &'static str,
+    mut receiver: mpsc::UnboundedReceiver<Command<T>>,
+) {
+    let mut pending: Option<(T, Instant)> = None;
+    // Set when the first change of a burst arrives, later ones can delay the
+    // write until `MAX_DELAY` after it.
+    let mut since = Instant::now();
+
+    loop {
+        let command = match &pending {
+            Some((_, deadline)) => {
+                match tokio::time::timeout_at(*deadline, receiver.recv()).await {
+                    Ok(command) => command,
+                    Err(_) => {
+                        write(&path, what, pending.take());
+                        continue;
+                    }
+                }
+            }
+            None => receiver.recv().await,
+        };
+
+        match command {
+            Some(Command::Save(value)) => {
+                let now = Instant::now();
+                if pending.is_none() {
+                    since = now;
+                }
+                pending = Some((value, (now + DEBOUNCE).min(since + MAX_DELAY)));
+            }
+            Some(Command::Flush(done)) => {
+                write(&path, what, pending.take());
+                let _ = done.send(());
+            }
+            None => {
+                write(&path, what, pending.take());
+                break;
+            }
+        }
+    }
+}
+
+fn write<T: Serialize>(path: &Path, what: &str, pending: Option<(T, Instant)>) {
+    let Some((value, _)) = pending else {
+        return;
+    };
+    let json = match serde_json::to_string(&value) {
+        Ok(json) => json,
+        Err(e) => {
+            println!("Failed to serialize {}: {}", what, e);
+            return;
+        }
+    };
+    let temp = path.with_extension("json.tmp");
+    if let Err(e) = fs::write(&temp, json).and_then(|()| fs::rename(&temp, path)) {
+        println!("Failed to save {}: {}", what, e);
+        let _ = fs::remove_file(&temp);
+    }
+}
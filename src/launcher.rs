@@ -0,0 +1,102 @@
+use std::sync::mpsc;
+use std::thread;
+
+/// Desktop entry the badge is attached to. Task managers match it against
+/// the launcher's `.desktop` file name.
+pub const DESKTOP_ID: &str = "whatsapp-qt.desktop";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BadgeState {
+    pub count: u32,
+    pub urgent: bool,
+}
+
+/// Publishes the unread count and urgency through the
+/// `com.canonical.Unity.LauncherEntry` D-Bus API, used by Plasma's task
+/// manager, Dash to Dock, Plank and others.
+pub struct LauncherBadge {
+    updates: mpsc::Sender<BadgeState>,
+    last: Option<BadgeState>,
+}
+
+impl LauncherBadge {
+    pub fn new() -> Self {
+        let (updates, receiver) = mpsc::channel();
+        thread::spawn(move || publish_loop(receiver));
+        Self {
+            updates,
+            last: None,
+        }
+    }
+
+    pub fn update(&mut self, state: BadgeState) {
+        if self.last == Some(state) {
+            return;
+        }
+        self.last = Some(state);
+        let _ = self.updates.send(state);
+    }
+}
+
+/// Parses the unread count WhatsApp Web puts in the page title, e.g. "(3) WhatsApp".
+pub fn unread_from_title(title: &str) -> u32 {
+    title
+        .strip_prefix('(')
+        .and_then(|rest| rest.split_once(')'))
+        .and_then(|(count, _)| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(target_os = "linux")]
+fn publish_loop(receiver: mpsc::Receiver<BadgeState>) {
+    use dbus::arg::{PropMap, RefArg, Variant};
+    use dbus::channel::Sender;
+    use dbus::message::Message;
+
+    let conn = match dbus::blocking::Connection::new_session() {
+        Ok(conn) => conn,
+        Err(e) => {
+            println!("Launcher badge disabled: {}", e);
+            return;
+        }
+    };
+    let app_uri = format!("application://{}", DESKTOP_ID);
+    let object_path = format!("/com/canonical/unity/launcherentry/{}", std::process::id());
+
+    for state in receiver {
+        let mut properties: PropMap = PropMap::new();
+        properties.insert(
+            "count".to_string(),
+            Variant(Box::new(i64::from(state.count)) as Box<dyn RefArg>),
+        );
+        properties.insert(
+            "count-visible".to_string(),
+            Variant(Box::new(state.count > 0) as Box<dyn RefArg>),
+        );
+        properties.insert(
+            "urgent".to_string(),
+            Variant(Box::new(state.urgent) as Box<dyn RefArg>),
+        );
+
+        let message = match Message::new_signal(
+            object_path.as_str(),
+            "com.canonical.Unity.LauncherEntry",
+            "Update",
+        ) {
+            Ok(message) => message.append2(app_uri.as_str(), properties),
+            Err(e) => {
+                println!("Failed to build launcher update: {}", e);
+                continue;
+            }
+        };
+        if conn.send(message).is_err() {
+            println!("Failed to publish launcher badge");
+        }
+        conn.channel().flush();
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn publish_loop(receiver: mpsc::Receiver<BadgeState>) {
+    for _ in receiver {}
+}
@@ -1,50 +1,57 @@
 use cstr::cstr;
-use dirs::config_dir;
 use qmetaobject::*;
-use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+mod avatar_cache;
+mod backup;
+mod bundle;
+mod call_log;
+mod color_scheme;
+mod config_cli;
+mod css;
+mod digest;
+mod json_writer;
+mod launcher;
 mod notification;
+mod notification_history;
+mod notification_hook;
+mod notification_server;
+mod page_bridge;
+mod paths;
+mod policy;
+mod profiles;
+mod push;
 mod qml_resources;
+mod runtime;
+mod secrets;
+mod settings;
+mod settings_watcher;
+mod settings_writer;
+mod speech;
+mod tray;
+mod validation;
 
-use notification::{NotificationService, NotificationSettings};
-
-const DARK_BASE_CSS: &str = r#"
-    /* Dark theme base styles */
-    body, #app, [data-testid="conversation-panel-body"] {
-        background-color: #1e1e1e !important;
-        color: #ffffff !important;
-        font-family: 'Fira Code', 'JetBrains Mono', 'monospace' !important;
-    }
-"#;
-const DARK_CHAT_CSS: &str = r#"
-    /* Dark theme chat styles */
-    [data-testid="chat-list"] {
-        background-color: #2d2d2d !important;
-    }
-    [data-testid="chat"] {
-        background-color: #3d3d3d !important;
-        border-bottom: 1px solid #555 !important;
-    }
-"#;
-const LIGHT_BASE_CSS: &str = r#"
-    /* Light theme base styles */
-    body, #app, [data-testid="conversation-panel-body"] {
-        background-color: #ffffff !important;
-        color: #000000 !important;
-        font-family: 'Fira Code', 'JetBrains Mono', 'monospace' !important;
-    }
-"#;
-const LIGHT_CHAT_CSS: &str = r#"
-    /* Light theme chat styles */
-    [data-testid="chat-list"] {
-        background-color: #f8f9fa !important;
-    }
-    [data-testid="chat"] {
-        background-color: #ffffff !important;
-        border-bottom: 1px solid #e9ecef !important;
-    }
-"#;
+use avatar_cache::{AvatarCache, AvatarLimits};
+use backup::{BackupScheduler, BackupSettings};
+use call_log::{CALL_LOG_FILE, CallLogModel, CallOutcome};
+use color_scheme::ColorScheme;
+use css::ThemeStrategy;
+use digest::Digest;
+use launcher::{BadgeState, LauncherBadge};
+use notification::{
+    CallAction, CallKind, IncomingCall, NotificationEvent, NotificationService,
+    NotificationSettings, SuppressionReason,
+};
+use notification_history::{HISTORY_FILE, HistoryEntry, HistoryKind, NotificationHistoryModel};
+use notification_hook::HookRunner;
+use notification_server::NotificationCapabilities;
+use page_bridge::{CallState, PageEvent};
+use push::PushForwarder;
+use secrets::KeyInfo;
+use settings::{AppSettings, SCHEMA_VERSION, TabInfo, WHATSAPP_SERVICE};
+use settings_writer::SettingsWriter;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 #[derive(QObject)]
 struct AppController {
@@ -56,8 +63,22 @@ struct AppController {
     download_path: qt_property!(QString; NOTIFY download_path_changed),
     download_path_changed: qt_signal!(),
     css_cache: qt_property!(QString;),
+    /// `light` or `dark` for the window chrome, empty for the system colours.
+    theme_variant: qt_property!(QString; NOTIFY theme_changed),
+    theme_window_color: qt_property!(QString; NOTIFY theme_changed),
+    /// WhatsApp's own scheme for `ThemeStrategy::Native`, `light`, `dark` or
+    /// `system`; empty when its pages are restyled with CSS instead.
+    native_scheme: qt_property!(QString; NOTIFY theme_changed),
+    theme_strategies: BTreeMap<String, ThemeStrategy>,
+    /// Desktop preference the `system` theme follows.
+    color_scheme: ColorScheme,
+    watching_color_scheme: bool,
     save_failed: qt_signal!(error: QString),
-    tab_added: qt_signal!(name: QString, icon: QString),
+    load_failed: qt_signal!(error: QString),
+    setting_rejected: qt_signal!(key: QString, reason: QString),
+    validation_failed: qt_signal!(field: QString, message: QString),
+    tab_added: qt_signal!(name: QString, icon: QString, storage: QString),
+    tabs_cleared: qt_signal!(),
     tab_removed: qt_signal!(index: i32),
     tab_renamed: qt_signal!(index: i32, new_name: QString),
     settings_saved: qt_signal!(),
@@ -73,18 +94,272 @@ struct AppController {
     notification_sound_enabled_changed: qt_signal!(),
     show_sender: qt_property!(bool; NOTIFY show_sender_changed),
     show_sender_changed: qt_signal!(),
+    do_not_disturb: qt_property!(bool; NOTIFY do_not_disturb_changed),
+    do_not_disturb_changed: qt_signal!(),
+    notification_history: qt_property!(RefCell<NotificationHistoryModel>; CONST),
+    call_log: qt_property!(RefCell<CallLogModel>; CONST),
+    notification_server: qt_property!(QString; NOTIFY notification_capabilities_changed),
+    notification_capabilities: qt_property!(QString; NOTIFY notification_capabilities_changed),
+    notification_capabilities_changed: qt_signal!(),
+    watching_notification_server: bool,
+    avatar_cache: AvatarCache,
+    hook_runner: HookRunner,
+    push_forwarder: PushForwarder,
+    window_active: bool,
+    window_visible: bool,
+    digest: Digest,
+    unread_count: qt_property!(i32; NOTIFY unread_count_changed),
+    unread_count_changed: qt_signal!(),
+    unread_per_tab: HashMap<i32, u32>,
+    launcher_badge: LauncherBadge,
+    raise_window: qt_signal!(tab_index: i32),
+    open_chat_requested: qt_signal!(tab_index: i32, chat: QString),
+    call_action_requested: qt_signal!(tab_index: i32, action: QString),
     tabs: Vec<TabInfo>,
+    settings_writer: SettingsWriter,
+    settings_encryption: Option<KeyInfo>,
+    watched_settings: HashSet<PathBuf>,
+    backup: BackupSettings,
+    backup_scheduler: BackupScheduler,
+    profile: qt_property!(QString; NOTIFY profile_changed),
+    profile_changed: qt_signal!(),
+
+    get_page_bridge_script: qt_method!(
+        fn get_page_bridge_script(&self) -> QString {
+            QString::from(qml_resources::PAGE_BRIDGE_JS)
+        }
+    ),
+    handle_web_notification: qt_method!(
+        fn handle_web_notification(
+            &mut self,
+            tab_index: i32,
+            account: QString,
+            title: QString,
+            body: QString,
+        ) {
+            let (title, body) = (title.to_string(), body.to_string());
+            // The call text is the body with the caller as title, or the
+            // other way round.
+            let call = match (CallKind::detect(&title), CallKind::detect(&body)) {
+                (Some(kind), _) => Some((kind, body.clone())),
+                (None, Some(kind)) => Some((kind, title.clone())),
+                (None, None) => None,
+            };
+            match call {
+                Some((kind, caller)) => self.ring(IncomingCall {
+                    tab_index,
+                    account: account.to_string(),
+                    caller,
+                    kind,
+                }),
+                None => self.notify_message(tab_index, account.to_string(), title, &body),
+            }
+        }
+    ),
+    handle_page_event: qt_method!(
+        fn handle_page_event(&mut self, tab_index: i32, account: QString, message: QString) {
+            match PageEvent::parse(&message.to_string()) {
+                Ok(PageEvent::Call {
+                    state: CallState::Ringing,
+                    kind,
+                    caller,
+                    ..
+                }) => self.ring(IncomingCall {
+                    tab_index,
+                    account: account.to_string(),
+                    caller,
+                    kind,
+                }),
+                Ok(PageEvent::Call {
+                    state: CallState::Ended,
+                    outcome,
+                    ..
+                }) => {
+                    self.dismiss_call(tab_index);
+                    self.call_log.borrow_mut().finish(tab_index, outcome);
+                }
+                Ok(PageEvent::Avatar { chat, url }) => {
+                    if let Some(storage) = self.storage_of(tab_index) {
+                        self.avatar_cache.update(storage, chat, url);
+                    }
+                }
+                Err(e) => println!(
+                    "Ignoring malformed page event from tab {}: {}",
+                    tab_index, e
+                ),
+            }
+        }
+    ),
+    answer_call: qt_method!(
+        fn answer_call(&mut self, tab_index: i32) {
+            self.on_call_action(tab_index, CallAction::Answer);
+        }
+    ),
+    decline_call: qt_method!(
+        fn decline_call(&mut self, tab_index: i32) {
+            self.on_call_action(tab_index, CallAction::Decline);
+        }
+    ),
+    open_history_entry: qt_method!(
+        fn open_history_entry(&mut self, row: i32) {
+            let target = self.notification_history.borrow().entry(row).map(|entry| {
+                (
+                    self.tab_of(&entry.storage, entry.tab_index),
+                    entry.sender.clone(),
+                )
+            });
+            if let Some((tab_index, sender)) = target {
+                self.notification_history.borrow_mut().mark_read(row);
+                // The account was removed since.
+                let Some(tab_index) = tab_index else {
+                    return;
+                };
+                self.raise_window(tab_index);
+                if !sender.is_empty() {
+                    self.open_chat_requested(tab_index, sender.into());
+                }
+            }
+        }
+    ),
+    call_back: qt_method!(
+        fn call_back(&mut self, row: i32) {
+            let target = self.call_log.borrow().record(row).map(|record| {
+                (
+                    self.tab_of(&record.storage, record.tab_index),
+                    record.caller.clone(),
+                )
+            });
+            if let Some((tab_index, caller)) = target {
+                self.call_log.borrow_mut().mark_seen(row);
+                let Some(tab_index) = tab_index else {
+                    return;
+                };
+                self.raise_window(tab_index);
+                if !caller.is_empty() {
+                    self.open_chat_requested(tab_index, caller.into());
+                }
+            }
+        }
+    ),
 
     test_notification: qt_method!(
-        fn test_notification(&self) {
+        fn test_notification(&self) -> QString {
             match self.notification_service.test_notification() {
-                Ok(_) => println!("Test notification sent successfully"),
-                Err(e) => println!("Failed to send test notification: {}", e),
+                Ok(report) => {
+                    println!("Test notification sent successfully");
+                    report.into()
+                }
+                Err(e) => {
+                    println!("Failed to send test notification: {}", e);
+                    e.to_string().into()
+                }
+            }
+        }
+    ),
+    set_tab_title: qt_method!(
+        fn set_tab_title(&mut self, tab_index: i32, title: QString) {
+            let unread = launcher::unread_from_title(&title.to_string());
+            if self.unread_per_tab.insert(tab_index, unread) != Some(unread) {
+                self.update_unread_count();
             }
         }
     ),
+    set_window_active: qt_method!(
+        fn set_window_active(&mut self, active: bool) {
+            self.window_active = active;
+        }
+    ),
+    set_window_visible: qt_method!(
+        fn set_window_visible(&mut self, visible: bool) {
+            let returned = visible && !self.window_visible;
+            self.window_visible = visible;
+            if returned
+                && self
+                    .notification_service
+                    .settings()
+                    .digest
+                    .deliver_on_return
+            {
+                self.deliver_digest();
+            }
+        }
+    ),
+    check_digest: qt_method!(
+        fn check_digest(&mut self) {
+            // Calls the page never reported as ended stop ringing as well.
+            let stale = self.call_log.borrow_mut().finish_stale();
+            for tab_index in stale {
+                self.dismiss_call(tab_index);
+            }
+            let settings = &self.notification_service.settings().digest;
+            if settings.enabled && self.digest.is_due(settings, chrono::Local::now()) {
+                self.deliver_digest();
+            }
+        }
+    ),
+    tab_icon_file: qt_method!(
+        fn tab_icon_file(&self, tab_index: i32) -> QString {
+            let Some(storage) = self.storage_of(tab_index) else {
+                return QString::default();
+            };
+            let path = self.avatar_cache.tab_icon_file(&storage);
+            if let Some(dir) = path.parent() {
+                if let Err(e) = fs::create_dir_all(dir) {
+                    println!("Failed to create {}: {}", dir.display(), e);
+                    return QString::default();
+                }
+            }
+            path.to_string_lossy().to_string().into()
+        }
+    ),
+    init_notification_server: qt_method!(
+        fn init_notification_server(&mut self) {
+            match notification_server::detect() {
+                Ok(capabilities) => self.apply_notification_capabilities(capabilities),
+                Err(e) => println!("Failed to query notification server: {}", e),
+            }
+
+            if self.watching_notification_server {
+                return;
+            }
+            self.watching_notification_server = true;
+            let qptr = QPointer::from(&*self);
+            let on_change = queued_callback(move |capabilities: NotificationCapabilities| {
+                if let Some(this) = qptr.as_pinned() {
+                    this.borrow_mut()
+                        .apply_notification_capabilities(capabilities);
+                }
+            });
+            notification_server::watch_server_restarts(on_change);
+        }
+    ),
+    init_color_scheme: qt_method!(
+        fn init_color_scheme(&mut self) {
+            match color_scheme::read() {
+                Ok(scheme) => self.apply_color_scheme(scheme),
+                Err(e) => println!("Failed to read the desktop colour scheme: {}", e),
+            }
+
+            if self.watching_color_scheme {
+                return;
+            }
+            self.watching_color_scheme = true;
+            let qptr = QPointer::from(&*self);
+            let on_change = queued_callback(move |scheme: ColorScheme| {
+                if let Some(this) = qptr.as_pinned() {
+                    this.borrow_mut().apply_color_scheme(scheme);
+                }
+            });
+            color_scheme::watch(on_change);
+        }
+    ),
     set_notifications_enabled: qt_method!(
         fn set_notifications_enabled(&mut self, enabled: bool) {
+            if self.notifications_enabled != enabled
+                && self.reject_locked("notification_settings.enabled")
+            {
+                return;
+            }
             self.notifications_enabled = enabled;
             self.notifications_enabled_changed();
             self.update_notification_settings();
@@ -93,6 +368,11 @@ struct AppController {
     ),
     set_show_message_notifications: qt_method!(
         fn set_show_message_notifications(&mut self, enabled: bool) {
+            if self.show_message_notifications != enabled
+                && self.reject_locked("notification_settings.show_message_notifications")
+            {
+                return;
+            }
             self.show_message_notifications = enabled;
             self.show_message_notifications_changed();
             self.update_notification_settings();
@@ -101,6 +381,11 @@ struct AppController {
     ),
     set_show_call_notifications: qt_method!(
         fn set_show_call_notifications(&mut self, enabled: bool) {
+            if self.show_call_notifications != enabled
+                && self.reject_locked("notification_settings.show_call_notifications")
+            {
+                return;
+            }
             self.show_call_notifications = enabled;
             self.show_call_notifications_changed();
             self.update_notification_settings();
@@ -109,6 +394,11 @@ struct AppController {
     ),
     set_notification_sound_enabled: qt_method!(
         fn set_notification_sound_enabled(&mut self, enabled: bool) {
+            if self.notification_sound_enabled != enabled
+                && self.reject_locked("notification_settings.sound_enabled")
+            {
+                return;
+            }
             self.notification_sound_enabled = enabled;
             self.notification_sound_enabled_changed();
             self.update_notification_settings();
@@ -117,12 +407,30 @@ struct AppController {
     ),
     set_show_sender: qt_method!(
         fn set_show_sender(&mut self, enabled: bool) {
+            if self.show_sender != enabled
+                && self.reject_locked("notification_settings.show_sender")
+            {
+                return;
+            }
             self.show_sender = enabled;
             self.show_sender_changed();
             self.update_notification_settings();
             self.save_settings();
         }
     ),
+    set_do_not_disturb: qt_method!(
+        fn set_do_not_disturb(&mut self, enabled: bool) {
+            if self.do_not_disturb != enabled
+                && self.reject_locked("notification_settings.do_not_disturb")
+            {
+                return;
+            }
+            self.do_not_disturb = enabled;
+            self.do_not_disturb_changed();
+            self.update_notification_settings();
+            self.save_settings();
+        }
+    ),
 
     get_theme_css: qt_method!(
         fn get_theme_css(&self) -> QString {
@@ -130,6 +438,13 @@ struct AppController {
         }
     ),
 
+    /// Names for the theme picker, see `css::available`.
+    list_themes: qt_method!(
+        fn list_themes(&self) -> QVariantList {
+            css::available().into_iter().map(QString::from).collect()
+        }
+    ),
+
     get_user_agent: qt_method!(
         fn get_user_agent(&self) -> QString {
             let user_agent = if cfg!(target_os = "linux") {
@@ -148,18 +463,44 @@ struct AppController {
 
     add_tab: qt_method!(
         fn add_tab(&mut self, name: QString, icon: QString) {
-            self.tab_added(name, icon);
+            if self.reject_locked("tabs") {
+                return;
+            }
+            let storage = self.free_tab_storage();
+            self.tabs.push(TabInfo {
+                name: name.to_string(),
+                icon: icon.to_string(),
+                storage: storage.clone(),
+            });
+            self.tab_added(name, icon, storage.into());
+            self.configure_backups();
             self.save_settings();
         }
     ),
     remove_tab: qt_method!(
         fn remove_tab(&mut self, index: i32) {
+            // The last account can not be removed, see the QML handler.
+            if index < 0 || index as usize >= self.tabs.len() || self.tabs.len() <= 1 {
+                return;
+            }
+            if self.reject_locked("tabs") {
+                return;
+            }
+            self.tabs.remove(index as usize);
             self.tab_removed(index);
+            self.forget_unread(index);
+            self.configure_backups();
             self.save_settings();
         }
     ),
     rename_tab: qt_method!(
         fn rename_tab(&mut self, index: i32, new_name: QString) {
+            if self.reject_locked(&format!("tabs.{}.name", index)) {
+                return;
+            }
+            if let Some(tab) = self.tabs.get_mut(index as usize) {
+                tab.name = new_name.to_string();
+            }
             self.tab_renamed(index, new_name);
             self.save_settings();
         }
@@ -173,14 +514,19 @@ struct AppController {
 
     set_theme: qt_method!(
         fn set_theme(&mut self, theme: QString) {
+            if self.theme != theme && self.reject_locked("theme") {
+                return;
+            }
+            if let Err(message) = validation::check_theme(&theme.to_string()) {
+                self.validation_failed("theme".into(), message.into());
+                return;
+            }
             println!("Setting theme to: {}", theme.to_string());
             self.theme = theme.clone();
 
             // Generate CSS before emitting signals
-            let css = self.generate_css(theme.to_string());
-            self.css_cache = QString::from(css.clone());
-            println!("Generated CSS Length: {}", css.len());
-            println!("CSS content: {}", css);
+            self.load_theme();
+            println!("Generated CSS Length: {}", self.css_cache.to_string().len());
             // Emit signals to update UI
             self.theme_changed();
             self.apply_theme_css(self.css_cache.clone());
@@ -188,93 +534,148 @@ struct AppController {
             self.save_settings();
         }
     ),
+    theme_strategy: qt_method!(
+        fn theme_strategy(&self, service: QString) -> QString {
+            self.strategy_for(&service.to_string()).as_str().into()
+        }
+    ),
+    /// `native` switches the service's own light or dark mode, `css`
+    /// restyles its pages with the whole theme.
+    set_theme_strategy: qt_method!(
+        fn set_theme_strategy(&mut self, service: QString, strategy: QString) {
+            let (service, strategy) = (service.to_string(), strategy.to_string());
+            let field = format!("theme_strategies.{}", service);
+            let Some(strategy) = ThemeStrategy::parse(&strategy) else {
+                self.validation_failed(field.into(), "Use native or css".into());
+                return;
+            };
+            if self.strategy_for(&service) == strategy {
+                return;
+            }
+            if self.reject_locked(&field) {
+                return;
+            }
+            self.theme_strategies.insert(service, strategy);
+            self.load_theme();
+            self.theme_changed();
+            self.apply_theme_css(self.css_cache.clone());
+            self.save_settings();
+        }
+    ),
 
     set_download_path: qt_method!(
         fn set_download_path(&mut self, path: QString) {
+            if self.download_path != path && self.reject_locked("download_path") {
+                return;
+            }
+            if let Err(message) = validation::check_download_path(&path.to_string()) {
+                self.validation_failed("download_path".into(), message.into());
+                return;
+            }
             self.download_path = path;
             self.download_path_changed();
             self.save_settings();
         }
     ),
+    locked_reason: qt_method!(
+        fn locked_reason(&self, key: QString) -> QString {
+            policy::get()
+                .lock_reason(&key.to_string())
+                .unwrap_or_default()
+                .into()
+        }
+    ),
+    list_profiles: qt_method!(
+        fn list_profiles(&self) -> QVariantList {
+            profiles::list().into_iter().map(QString::from).collect()
+        }
+    ),
+    /// Saves the current profile and applies `name`. A profile that doesn't
+    /// exist yet starts as a copy of the current one.
+    switch_profile: qt_method!(
+        fn switch_profile(&mut self, name: QString) {
+            let name = name.to_string();
+            if self.profile.to_string() == name {
+                return;
+            }
+            if !profiles::is_valid_name(&name) {
+                self.load_failed(
+                    format!(
+                        "Invalid profile name {:?}, use letters, digits, - and _",
+                        name
+                    )
+                    .into(),
+                );
+                return;
+            }
+            println!("Switching to settings profile {}", name);
+            let create = !profiles::exists(&name);
+            self.save_settings();
+            self.profile = name.clone().into();
+            profiles::set_active(&name);
+            if create {
+                self.save_settings();
+            } else {
+                let s = self.read_profile_settings().unwrap_or_default();
+                self.apply_settings(s);
+            }
+            if let Some(cfg) = profiles::dir(&name) {
+                self.watch_settings(cfg);
+            }
+            self.profile_changed();
+        }
+    ),
     save_settings: qt_method!(
         fn save_settings(&self) {
-            let mut settings = AppSettings {
+            let Some(dir) = profiles::dir(&self.profile.to_string()) else {
+                self.save_failed("Could not find config directory".into());
+                return;
+            };
+            let settings = AppSettings {
+                schema_version: SCHEMA_VERSION,
                 theme: self.theme.to_string(),
+                theme_strategies: self.theme_strategies.clone(),
                 download_path: self.download_path.to_string(),
                 current_tab: self.current_tab,
-                notification_settings: NotificationSettings {
-                    enabled: self.notifications_enabled,
-                    show_message_notifications: self.show_message_notifications,
-                    show_call_notifications: self.show_call_notifications,
-                    sound_enabled: self.notification_sound_enabled,
-                    show_sender: self.show_sender,
-                },
-                tabs: self
-                    .tabs
-                    .iter()
-                    .map(|t| TabInfo {
-                        name: t.name.to_string(),
-                        icon: t.icon.to_string(),
-                    })
-                    .collect(),
+                notification_settings: self.notification_settings(),
+                tabs: self.tabs.clone(),
+                backup: self.backup.clone(),
+                encryption: self.settings_encryption.clone(),
             };
+            // Saved anyway, one bad field would otherwise keep every later
+            // change from being written.
+            self.report_invalid(&settings);
 
-            settings.encrypt_fields();
-            let cfg_path = get_config_path();
-            let async_settings = settings.clone();
-
-            if let Some(cfg) = get_config_path() {
-                if let Ok(json) = serde_json::to_string_pretty(&settings) {
-                    let _ = std::fs::write(cfg.join("settings.json"), &json);
-                }
-            }
-
-            if let Some(cfg) = get_config_path() {
-                if let Ok(json) = serde_json::to_string_pretty(&settings) {
-                    match fs::write(cfg.join("settings.json"), &json) {
-                        Ok(_) => self.settings_saved(),
-                        Err(e) => self.save_failed(QString::from(e.to_string())),
+            let qptr = QPointer::from(&*self);
+            let report = queued_callback(move |result: Result<(), String>| {
+                if let Some(this) = qptr.as_pinned() {
+                    let this = this.borrow();
+                    match result {
+                        Ok(()) => this.settings_saved(),
+                        Err(e) => this.save_failed(e.into()),
                     }
-                } else {
-                    self.save_failed(QString::from("Failed to serialize settings"));
                 }
-            } else {
-                self.save_failed(QString::from("Could not find config directory"));
-            }
+            });
+            self.settings_writer.save(dir, &settings, Box::new(report));
         }
     ),
     load_settings: qt_method!(
         fn load_settings(&mut self) {
-            if let Some(cfg) = get_config_path() {
-                if let Ok(data) = fs::read_to_string(cfg.join("settings.json")) {
-                    if let Ok(s) = serde_json::from_str::<AppSettings>(&data) {
-                        self.theme = s.theme.into();
-                        self.download_path = s.download_path.into();
-                        self.current_tab = s.current_tab;
-
-                        // Load notification settings
-                        self.notifications_enabled = s.notification_settings.enabled;
-                        self.show_message_notifications =
-                            s.notification_settings.show_message_notifications;
-                        self.show_call_notifications =
-                            s.notification_settings.show_call_notifications;
-                        self.notification_sound_enabled = s.notification_settings.sound_enabled;
-                        self.show_sender = s.notification_settings.show_sender;
-
-                        // Emit all change signals
-                        self.theme_changed();
-                        self.download_path_changed();
-                        self.current_tab_changed();
-                        self.notifications_enabled_changed();
-                        self.show_message_notifications_changed();
-                        self.show_call_notifications_changed();
-                        self.notification_sound_enabled_changed();
-                        self.show_sender_changed();
-
-                        // Update notification service
-                        self.update_notification_settings();
-                    }
-                }
+            match self.read_profile_settings() {
+                Some(s) => self.apply_settings(s),
+                None => self.configure_backups(),
+            }
+            if let Some(cfg) = profiles::dir(&self.profile.to_string()) {
+                self.watch_settings(cfg);
+            }
+
+            if let Some(data) = paths::data_dir() {
+                // Before loading, which trims to the retention.
+                self.apply_history_retention();
+                self.notification_history
+                    .borrow_mut()
+                    .load(data.join(HISTORY_FILE));
+                self.call_log.borrow_mut().load(data.join(CALL_LOG_FILE));
             }
         }
     ),
@@ -288,26 +689,36 @@ impl Default for AppController {
             current_tab_changed: Default::default(),
             theme: "system".into(),
             theme_changed: Default::default(),
-            download_path: dirs::download_dir()
-                .unwrap_or_else(|| std::env::current_dir().unwrap())
-                .to_string_lossy()
-                .to_string()
-                .into(),
+            download_path: settings::default_download_path().into(),
             download_path_changed: Default::default(),
             css_cache: QString::default(),
+            theme_variant: QString::default(),
+            theme_window_color: QString::default(),
+            native_scheme: QString::default(),
+            theme_strategies: settings::default_theme_strategies(),
+            color_scheme: ColorScheme::default(),
+            watching_color_scheme: false,
             save_failed: Default::default(),
+            load_failed: Default::default(),
+            setting_rejected: Default::default(),
+            validation_failed: Default::default(),
+            locked_reason: Default::default(),
             tab_added: Default::default(),
+            tabs_cleared: Default::default(),
             tab_removed: Default::default(),
             tab_renamed: Default::default(),
             settings_saved: Default::default(),
             apply_theme_css: Default::default(),
             get_theme_css: Default::default(),
+            list_themes: Default::default(),
             get_user_agent: Default::default(),
             add_tab: Default::default(),
             remove_tab: Default::default(),
             rename_tab: Default::default(),
             set_current_tab: Default::default(),
             set_theme: Default::default(),
+            theme_strategy: Default::default(),
+            set_theme_strategy: Default::default(),
             set_download_path: Default::default(),
             save_settings: Default::default(),
             load_settings: Default::default(),
@@ -322,121 +733,664 @@ impl Default for AppController {
             notification_sound_enabled_changed: Default::default(),
             show_sender: true,
             show_sender_changed: Default::default(),
+            do_not_disturb: false,
+            do_not_disturb_changed: Default::default(),
+            notification_history: Default::default(),
+            call_log: Default::default(),
+            call_back: Default::default(),
+            raise_window: Default::default(),
+            open_chat_requested: Default::default(),
+            call_action_requested: Default::default(),
+            get_page_bridge_script: Default::default(),
+            handle_web_notification: Default::default(),
+            handle_page_event: Default::default(),
+            answer_call: Default::default(),
+            decline_call: Default::default(),
+            open_history_entry: Default::default(),
+            notification_server: QString::from("Not detected"),
+            notification_capabilities: QString::default(),
+            notification_capabilities_changed: Default::default(),
+            watching_notification_server: false,
+            avatar_cache: AvatarCache::new(paths::cache_dir()),
+            hook_runner: HookRunner::new(),
+            push_forwarder: PushForwarder::start(
+                paths::data_dir().map(|data| data.join(push::QUEUE_FILE)),
+            ),
+            window_active: true,
+            window_visible: true,
+            digest: Digest::new(),
+            set_window_visible: Default::default(),
+            check_digest: Default::default(),
+            unread_count: 0,
+            unread_count_changed: Default::default(),
+            unread_per_tab: HashMap::new(),
+            launcher_badge: LauncherBadge::new(),
+            set_tab_title: Default::default(),
+            set_window_active: Default::default(),
+            tab_icon_file: Default::default(),
+            init_notification_server: Default::default(),
+            init_color_scheme: Default::default(),
             test_notification: Default::default(),
             set_notifications_enabled: Default::default(),
             set_show_message_notifications: Default::default(),
             set_show_call_notifications: Default::default(),
             set_notification_sound_enabled: Default::default(),
             set_show_sender: Default::default(),
+            set_do_not_disturb: Default::default(),
             tabs: Vec::new(),
+            settings_writer: SettingsWriter::start(),
+            settings_encryption: None,
+            watched_settings: HashSet::new(),
+            backup: BackupSettings::default(),
+            backup_scheduler: BackupScheduler::start(),
+            profile: profiles::startup_profile().into(),
+            profile_changed: Default::default(),
+            list_profiles: Default::default(),
+            switch_profile: Default::default(),
         }
     }
 }
 
 impl AppController {
-    fn generate_css(&self, theme: String) -> String {
-        let mut css = String::new();
-        println!("Generating CSS for theme: {}", theme);
-
-        if theme == "dark" {
-            css.push_str(DARK_BASE_CSS);
-            css.push_str(DARK_CHAT_CSS);
-            // Add more comprehensive dark theme CSS
-            css.push_str(
-                r#"
-                /* Additional dark theme styles */
-                ._3OtEr, .app, #app, .app-wrapper-web, [data-testid="app-wrapper"] {
-                    background-color: #1e1e1e !important;
-                }
-                ._3j7s9 {
-                    background-color: #2d2d2d !important;
-                }
-                /* WhatsApp specific dark theme */
-                [data-testid="chatlist-header"], [data-testid="chat-header"] {
-                    background-color: #2d2d2d !important;
-                }
-            "#,
+    /// Applies loaded settings, emitting change signals only for the values
+    /// that differ from the current ones.
+    fn apply_settings(&mut self, s: AppSettings) {
+        let theme = QString::from(s.theme);
+        if self.theme != theme || self.theme_strategies != s.theme_strategies {
+            self.theme = theme;
+            self.theme_strategies = s.theme_strategies;
+            self.load_theme();
+            self.apply_theme_css(self.css_cache.clone());
+            self.theme_changed();
+        }
+        let download_path = QString::from(s.download_path);
+        if self.download_path != download_path {
+            self.download_path = download_path;
+            self.download_path_changed();
+        }
+        if self.current_tab != s.current_tab {
+            self.current_tab = s.current_tab;
+            self.current_tab_changed();
+        }
+
+        // Load notification settings
+        let n = &s.notification_settings;
+        if self.notifications_enabled != n.enabled {
+            self.notifications_enabled = n.enabled;
+            self.notifications_enabled_changed();
+        }
+        if self.show_message_notifications != n.show_message_notifications {
+            self.show_message_notifications = n.show_message_notifications;
+            self.show_message_notifications_changed();
+        }
+        if self.show_call_notifications != n.show_call_notifications {
+            self.show_call_notifications = n.show_call_notifications;
+            self.show_call_notifications_changed();
+        }
+        if self.notification_sound_enabled != n.sound_enabled {
+            self.notification_sound_enabled = n.sound_enabled;
+            self.notification_sound_enabled_changed();
+        }
+        if self.show_sender != n.show_sender {
+            self.show_sender = n.show_sender;
+            self.show_sender_changed();
+        }
+        if self.do_not_disturb != n.do_not_disturb {
+            self.do_not_disturb = n.do_not_disturb;
+            self.do_not_disturb_changed();
+        }
+        self.notification_service
+            .update_settings(s.notification_settings);
+
+        // Update notification service
+        self.update_notification_settings();
+        self.apply_history_retention();
+
+        self.settings_encryption = s.encryption;
+
+        // Restore the saved accounts: renames in place, then additions or
+        // removals at the end. Different accounts, e.g. from another
+        // profile, rebuild the tabs.
+        let same_accounts = self
+            .tabs
+            .iter()
+            .zip(&s.tabs)
+            .all(|(old, new)| old.storage == new.storage);
+        if !same_accounts {
+            self.tabs_cleared();
+            self.tabs.clear();
+            // The new tabs report their own counts once their pages load.
+            self.unread_per_tab.clear();
+            self.update_unread_count();
+        }
+        for (index, tab) in s.tabs.iter().enumerate().take(self.tabs.len()) {
+            if self.tabs[index].name != tab.name {
+                self.tab_renamed(index as i32, tab.name.clone().into());
+            }
+        }
+        for tab in s.tabs.iter().skip(self.tabs.len()) {
+            self.tab_added(
+                tab.name.clone().into(),
+                tab.icon.clone().into(),
+                tab.storage.clone().into(),
             );
-        } else if theme == "light" {
-            css.push_str(LIGHT_BASE_CSS);
-            css.push_str(LIGHT_CHAT_CSS);
-            // Add more comprehensive light theme CSS
-            css.push_str(
-                r#"
-                /* Additional light theme styles */
-                ._3OtEr, .app, #app, .app-wrapper-web, [data-testid="app-wrapper"] {
-                    background-color: #ffffff !important;
+        }
+        for index in (s.tabs.len()..self.tabs.len()).rev() {
+            self.tab_removed(index as i32);
+            self.forget_unread(index as i32);
+        }
+        self.tabs = s.tabs;
+
+        self.backup = s.backup;
+        self.configure_backups();
+    }
+
+    fn apply_history_retention(&self) {
+        let settings = self.notification_service.settings();
+        self.notification_history
+            .borrow_mut()
+            .set_retention(settings.history_limit, settings.history_retention_days);
+    }
+
+    /// Hands the backup schedule and the accounts' web profiles to the
+    /// backup task.
+    fn configure_backups(&self) {
+        let accounts = self.tabs.iter().map(|tab| tab.storage.clone()).collect();
+        self.backup_scheduler
+            .configure(self.backup.clone(), accounts);
+    }
+
+    /// Emits `validation_failed` for each problem in `settings`. Returns
+    /// whether they are valid.
+    fn report_invalid(&self, settings: &AppSettings) -> bool {
+        let errors = validation::validate(settings);
+        for error in &errors {
+            println!("Invalid setting {}", error);
+            self.validation_failed(error.field.clone().into(), error.message.clone().into());
+        }
+        errors.is_empty()
+    }
+
+    /// Reports and refuses a change to a key the policy locks.
+    fn reject_locked(&self, key: &str) -> bool {
+        let Some(reason) = policy::get().lock_reason(key) else {
+            return false;
+        };
+        println!("Not changing {}: {}", key, reason);
+        self.setting_rejected(key.into(), reason.into());
+        true
+    }
+
+    /// Reads and decrypts the current profile's settings, reporting errors.
+    /// `None` when there is nothing to apply or the file is invalid.
+    fn read_profile_settings(&self) -> Option<AppSettings> {
+        let cfg = profiles::dir(&self.profile.to_string())?;
+        match settings::load(&cfg) {
+            Ok(Some(mut s)) => {
+                if let Err(e) = s.decrypt_fields_unlocked() {
+                    println!("{}", e);
+                    self.load_failed(e.to_string().into());
                 }
-                ._3j7s9 {
-                    background-color: #f8f9fa !important;
+                // Rejected like on a reload, the current values stay.
+                self.report_invalid(&s).then_some(s)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                println!("{}", e);
+                self.load_failed(e.to_string().into());
+                None
+            }
+        }
+    }
+
+    /// Web profile directory of the tab at `tab_index`, which identifies the
+    /// account wherever the tab moves.
+    fn storage_of(&self, tab_index: i32) -> Option<String> {
+        usize::try_from(tab_index)
+            .ok()
+            .and_then(|index| self.tabs.get(index))
+            .map(|tab| tab.storage.clone())
+    }
+
+    /// Current position of the account `storage`, `None` once it was
+    /// removed. Records without one fall back to their `tab_index`.
+    fn tab_of(&self, storage: &str, tab_index: i32) -> Option<i32> {
+        if storage.is_empty() {
+            return Some(tab_index);
+        }
+        self.tabs
+            .iter()
+            .position(|tab| tab.storage == storage)
+            .map(|index| index as i32)
+    }
+
+    /// First `whatsapp_tab_<n>` web profile neither used by a tab nor left
+    /// on disk by another account.
+    fn free_tab_storage(&self) -> String {
+        let web_profiles = paths::profiles_dir();
+        (0..)
+            .map(settings::tab_storage)
+            .find(|storage| {
+                !self.tabs.iter().any(|tab| &tab.storage == storage)
+                    && !web_profiles
+                        .as_ref()
+                        .is_some_and(|dir| dir.join(storage).exists())
+            })
+            .expect("storage names are unbounded")
+    }
+
+    /// Reloads `settings.json` in `cfg` whenever it is changed outside the
+    /// app. Each profile directory is watched once.
+    fn watch_settings(&mut self, cfg: PathBuf) {
+        if !self.watched_settings.insert(cfg.clone()) {
+            return;
+        }
+        let qptr = QPointer::from(&*self);
+        let dir = cfg.clone();
+        let reload = queued_callback(move |()| {
+            if let Some(this) = qptr.as_pinned() {
+                this.borrow_mut().reload_settings(&dir);
+            }
+        });
+        settings_watcher::watch(cfg, self.settings_writer.known_content(), reload);
+    }
+
+    /// Re-applies an externally edited settings file. When it does not parse
+    /// or decrypt, the error is reported and the current settings are kept.
+    /// Changes to profiles that aren't active are picked up on switching.
+    fn reload_settings(&mut self, cfg: &Path) {
+        if profiles::dir(&self.profile.to_string()).as_deref() != Some(cfg) {
+            return;
+        }
+        let result = settings::reload(cfg)
+            .map_err(|e| e.to_string())
+            .and_then(|s| match s {
+                Some(mut s) => s
+                    .decrypt_fields_unlocked()
+                    .map(|()| Some(s))
+                    .map_err(|e| e.to_string()),
+                None => Ok(None),
+            });
+        match result {
+            Ok(Some(s)) => {
+                if self.report_invalid(&s) {
+                    self.apply_settings(s);
                 }
-            "#,
-            );
+            }
+            Ok(None) => {}
+            Err(e) => {
+                println!("Ignoring changed settings: {}", e);
+                self.load_failed(e.into());
+            }
         }
+    }
 
-        println!("Generated CSS: {}", css);
-        css
+    fn strategy_for(&self, service: &str) -> ThemeStrategy {
+        self.theme_strategies
+            .get(service)
+            .copied()
+            .unwrap_or_default()
     }
 
-    fn update_notification_settings(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let settings = NotificationSettings {
+    /// Renders the selected theme into `css_cache`, `native_scheme` and the
+    /// chrome properties. A theme that can't be loaded leaves the page
+    /// unstyled, validation has already told the user why.
+    fn load_theme(&mut self) {
+        let name = self.theme.to_string();
+        // `system` uses the bundled theme named after the desktop's scheme.
+        let name = if name == css::SYSTEM_THEME {
+            self.color_scheme
+                .variant()
+                .map(|variant| variant.as_str().to_string())
+        } else {
+            Some(name)
+        };
+        println!("Generating CSS for theme: {:?}", name);
+        let theme = match name.map(|name| css::load(&name)) {
+            Some(Ok(theme)) => Some(theme),
+            Some(Err(e)) => {
+                println!("{}", e);
+                None
+            }
+            None => None,
+        };
+        let strategy = self.strategy_for(WHATSAPP_SERVICE);
+        self.native_scheme = match (strategy, &theme) {
+            (ThemeStrategy::Css, _) => QString::default(),
+            (ThemeStrategy::Native, Some(theme)) => theme.variant.as_str().into(),
+            (ThemeStrategy::Native, None) => css::SYSTEM_THEME.into(),
+        };
+        match theme {
+            Some(theme) => {
+                self.css_cache = theme.css_for(strategy).into();
+                self.theme_variant = theme.variant.as_str().into();
+                self.theme_window_color = theme.window_color.unwrap_or_default().into();
+            }
+            None => {
+                self.css_cache = QString::default();
+                self.theme_variant = QString::default();
+                self.theme_window_color = QString::default();
+            }
+        }
+    }
+
+    /// Restyles the pages and chrome when the desktop switches between light
+    /// and dark while the `system` theme is selected.
+    fn apply_color_scheme(&mut self, scheme: ColorScheme) {
+        if self.color_scheme == scheme {
+            return;
+        }
+        println!("Desktop colour scheme is now {:?}", scheme);
+        self.color_scheme = scheme;
+        if self.theme.to_string() == css::SYSTEM_THEME {
+            self.load_theme();
+            self.apply_theme_css(self.css_cache.clone());
+            self.theme_changed();
+        }
+    }
+
+    /// Notification settings with the QML-facing properties applied on top of
+    /// the fields that only live in `settings.json` (rules, limits).
+    fn notification_settings(&self) -> NotificationSettings {
+        NotificationSettings {
             enabled: self.notifications_enabled,
             show_message_notifications: self.show_message_notifications,
             show_call_notifications: self.show_call_notifications,
             sound_enabled: self.notification_sound_enabled,
             show_sender: self.show_sender,
-        };
-        let _icon_path = std::env::current_dir()
-            .unwrap()
-            .join("resources/icons/tray.png")
-            .to_string_lossy()
-            .to_string();
+            do_not_disturb: self.do_not_disturb,
+            ..self.notification_service.settings().clone()
+        }
+    }
+
+    fn update_notification_settings(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let settings = self.notification_settings();
+        self.avatar_cache.set_limits(AvatarLimits {
+            max_account_bytes: u64::from(settings.avatar_cache_limit_mb) * 1024 * 1024,
+            max_age_days: settings.avatar_max_age_days,
+        });
+        self.hook_runner.configure(settings.command_hook.clone());
+        self.push_forwarder.configure(settings.push_targets.clone());
         self.notification_service.update_settings(settings);
         Ok(()) // ← REQUIRED!
     }
-}
-impl AppSettings {
-    pub fn encrypt_fields(&mut self) {
-        // TODO: Add encryption logic later
-        // For now, this does nothing
+
+    fn apply_notification_capabilities(&mut self, capabilities: NotificationCapabilities) {
+        self.notification_server = capabilities.server_label().into();
+        self.notification_capabilities = capabilities.summary().into();
+        self.notification_service.set_capabilities(capabilities);
+        self.notification_capabilities_changed();
+    }
+
+    /// Sender avatar, account tab icon or the application icon, in that order.
+    fn notification_icon(&self, tab_index: i32, sender: &str) -> Option<String> {
+        self.storage_of(tab_index)
+            .and_then(|storage| self.avatar_cache.icon_for(&storage, sender))
+            .map(|path| path.to_string_lossy().to_string())
+            .or_else(notification::tray_icon_path)
+    }
+
+    /// Hands an incoming message or call to the command hook and push targets.
+    fn dispatch_event(&mut self, event: NotificationEvent) {
+        self.push_forwarder
+            .forward(event.clone(), !self.window_active);
+        self.hook_runner.run(event.clone());
+
+        // Collect what the user missed while hidden or in DND, but not
+        // chats they muted on purpose.
+        let missed = match event.suppressed {
+            Some(SuppressionReason::Rule) => false,
+            Some(SuppressionReason::DoNotDisturb) => true,
+            _ => !self.window_visible,
+        };
+        if missed && self.notification_service.settings().digest.enabled {
+            self.digest.collect(event);
+        }
+    }
+
+    fn deliver_digest(&mut self) {
+        let settings = self.notification_service.settings();
+        let output = settings.digest.output;
+        // Keep collecting while a notification-only digest would be silenced.
+        if settings.do_not_disturb && !output.markdown() {
+            return;
+        }
+        let Some(report) = self.digest.take() else {
+            return;
+        };
+
+        if output.notification() && !settings.do_not_disturb {
+            if let Err(e) = self.notification_service.send_notification(
+                &report.title(),
+                &report.summary(),
+                notification::tray_icon_path().as_deref(),
+            ) {
+                println!("Failed to show digest notification: {}", e);
+            }
+        }
+        if output.markdown() {
+            if let Some(data) = paths::data_dir() {
+                if let Err(e) = report.write_markdown(&data.join(digest::DIGEST_FILE)) {
+                    println!("Failed to write digest: {}", e);
+                }
+            }
+        }
+    }
+
+    fn notify_message(&mut self, tab_index: i32, account: String, sender: String, body: &str) {
+        let icon_path = self.notification_icon(tab_index, &sender);
+        let suppressed = match self.notification_service.notify_message(
+            &account,
+            &sender,
+            body,
+            icon_path.as_deref(),
+        ) {
+            Ok(delivery) => delivery.suppression(),
+            Err(e) => {
+                println!("Failed to show message notification: {}", e);
+                None
+            }
+        };
+        self.dispatch_event(NotificationEvent::message(
+            tab_index, &account, &sender, body, suppressed,
+        ));
+        self.notification_history
+            .borrow_mut()
+            .record(HistoryEntry::new(
+                HistoryKind::Message,
+                tab_index,
+                self.storage_of(tab_index).unwrap_or_default(),
+                account,
+                sender,
+                body,
+                suppressed,
+            ));
+    }
+
+    fn ring(&mut self, call: IncomingCall) {
+        let (tab_index, kind) = (call.tab_index, call.kind);
+        let storage = self.storage_of(tab_index).unwrap_or_default();
+        self.call_log
+            .borrow_mut()
+            .start(call.clone(), storage.clone());
+        if self.notification_service.is_ringing(tab_index) {
+            return;
+        }
+        let entry = HistoryEntry::new(
+            HistoryKind::Call,
+            tab_index,
+            storage,
+            call.account.clone(),
+            call.caller.clone(),
+            &format!("Incoming {} call", call.kind.label()),
+            None,
+        );
+        let qptr = QPointer::from(&*self);
+        let on_action = queued_callback(move |(tab_index, action): (i32, CallAction)| {
+            if let Some(this) = qptr.as_pinned() {
+                this.borrow_mut().on_call_action(tab_index, action);
+            }
+        });
+
+        let icon_path = self.notification_icon(tab_index, &call.caller);
+        let suppressed = match self.notification_service.ring(
+            call,
+            icon_path.as_deref(),
+            move |index, action| on_action((index, action)),
+        ) {
+            Ok(delivery) => delivery.suppression(),
+            Err(e) => {
+                println!("Failed to show call notification: {}", e);
+                None
+            }
+        };
+        self.dispatch_event(NotificationEvent::call(
+            tab_index,
+            &entry.account,
+            &entry.sender,
+            kind,
+            suppressed,
+        ));
+        self.notification_history.borrow_mut().record(HistoryEntry {
+            suppressed,
+            ..entry
+        });
+        if self.notification_service.is_ringing(tab_index) {
+            self.update_launcher_badge();
+            self.raise_window(tab_index);
+        }
+    }
+
+    fn dismiss_call(&mut self, tab_index: i32) {
+        self.notification_service.dismiss_call(tab_index);
+        self.update_launcher_badge();
+    }
+
+    /// Drops the unread count of the removed tab `index`; the counts of the
+    /// tabs after it move down with them.
+    fn forget_unread(&mut self, index: i32) {
+        self.unread_per_tab = self
+            .unread_per_tab
+            .drain()
+            .filter(|&(tab, _)| tab != index)
+            .map(|(tab, unread)| (if tab > index { tab - 1 } else { tab }, unread))
+            .collect();
+        self.update_unread_count();
+    }
+
+    fn update_unread_count(&mut self) {
+        let total: u32 = self.unread_per_tab.values().sum();
+        if total as i32 != self.unread_count {
+            self.unread_count = total as i32;
+            self.unread_count_changed();
+        }
+        self.update_launcher_badge();
+    }
+
+    fn update_launcher_badge(&mut self) {
+        let state = BadgeState {
+            count: self.unread_per_tab.values().sum(),
+            urgent: self.notification_service.has_ringing_calls(),
+        };
+        self.launcher_badge.update(state);
+    }
+
+    fn on_call_action(&mut self, tab_index: i32, action: CallAction) {
+        self.dismiss_call(tab_index);
+        match action {
+            CallAction::Answer => {
+                self.call_log
+                    .borrow_mut()
+                    .set_outcome(tab_index, CallOutcome::Answered);
+                self.raise_window(tab_index);
+                self.call_action_requested(tab_index, QString::from("answer"));
+            }
+            CallAction::Decline => {
+                self.call_log
+                    .borrow_mut()
+                    .set_outcome(tab_index, CallOutcome::Declined);
+                self.call_action_requested(tab_index, QString::from("decline"));
+            }
+            CallAction::Dismissed => {}
+        }
     }
 }
-#[derive(Serialize, Deserialize, Clone)]
-struct AppSettings {
-    theme: String,
-    download_path: String,
-    current_tab: i32,
-    notification_settings: NotificationSettings,
-    tabs: Vec<TabInfo>, // NEW FIELD
-}
-#[derive(Serialize, Deserialize, Clone)]
-struct TabInfo {
-    name: String,
-    icon: String,
+/// Value of `--<name> <value>` or `--<name>=<value>`.
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let prefix = format!("--{}=", name);
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if *arg == flag {
+            args.get(i + 1).cloned()
+        } else {
+            arg.strip_prefix(&prefix).map(str::to_string)
+        }
+    })
 }
-fn get_config_path() -> Option<PathBuf> {
-    if let Some(mut path) = config_dir() {
-        path.push("WhatsAppDesktop");
-        let _ = fs::create_dir_all(&path);
-        Some(path)
-    } else {
-        None
+
+/// Commands that run without starting Qt.
+const SUBCOMMANDS: &[&str] = &["config", "bundle", "backup"];
+
+/// Position of the subcommand, the first argument that is neither an option
+/// nor the value of one.
+fn subcommand(args: &[String]) -> Option<usize> {
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--config-dir" | "--profile" => i += 2,
+            arg if arg.starts_with("--") => i += 1,
+            _ => return Some(i),
+        }
     }
+    None
 }
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     use std::sync::{Arc, Mutex};
     use std::thread;
-    use tray_item::TrayItem;
+    use tray_item::{IconSource, TrayItem};
+
+    let args: Vec<String> = std::env::args().collect();
+    paths::init(arg_value(&args, "config-dir").map(PathBuf::from));
+    profiles::init(arg_value(&args, "profile"));
+    if args.iter().any(|arg| arg == "--rollback-migration") {
+        paths::rollback_migration()?;
+        println!("Restored the previous config layout");
+        return Ok(());
+    }
+    paths::migrate_legacy();
+
+    // Anything else, e.g. Qt's `-platform wayland`, is left to Qt.
+    if let Some(i) = subcommand(&args).filter(|&i| SUBCOMMANDS.contains(&args[i].as_str())) {
+        let result = match args[i].as_str() {
+            "config" => config_cli::run(&args[i + 1..]),
+            "bundle" => bundle::run(&args[i + 1..]),
+            _ => backup::run_command(&args[i + 1..]),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Open the settings key now, a keyring or passphrase prompt would
+    // otherwise block the GUI thread on the first load or save.
+    if let Some(dir) = profiles::dir(&profiles::startup_profile()) {
+        secrets::cipher(settings::read_encryption(&dir).as_ref());
+    }
 
     let should_show = Arc::new(Mutex::new(true));
     let should_show_clone = Arc::clone(&should_show);
-    let exe_dir = std::env::current_exe()?.parent().unwrap().to_path_buf();
-    let icon_path = exe_dir.join("resources/icons/tray.png");
+    let icon_path = notification::tray_icon_path().unwrap_or_default();
 
+    let missed_calls = tray::missed_call_updates();
     thread::spawn(move || {
-        let mut tray = TrayItem::new("WhatsApp", "&icon_path").unwrap();
+        // The tray keeps the name for as long as the app runs.
+        let idle_icon: &'static str = Box::leak(icon_path.into_boxed_str());
+        let mut tray = TrayItem::new("WhatsApp", IconSource::Resource(idle_icon)).unwrap();
 
-        tray.add_label("WhatsApp Desktop").unwrap();
+        let label = tray.add_label_with_id(&tray::label(0)).unwrap();
 
         let show_clone = Arc::clone(&should_show_clone);
         tray.add_menu_item("Show/Hide", move || {
@@ -453,8 +1407,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .unwrap();
 
-        loop {
-            std::thread::sleep(std::time::Duration::from_secs(1));
+        for missed in missed_calls {
+            let icon = if missed > 0 {
+                tray::MISSED_CALL_ICON
+            } else {
+                idle_icon
+            };
+            if let Err(e) = tray.set_icon(IconSource::Resource(icon)) {
+                println!("Failed to update tray icon: {:?}", e);
+            }
+            if let Err(e) = tray.set_label(&tray::label(missed), label) {
+                println!("Failed to update tray label: {:?}", e);
+            }
         }
     });
 
@@ -473,11 +1437,30 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         "SettingsDialogQML".into(),
         QVariant::from(QString::from(qml_resources::SETTINGS_DIALOG_QML)),
     );
+    engine.set_property(
+        "NotificationCenterQML".into(),
+        QVariant::from(QString::from(qml_resources::NOTIFICATION_CENTER_QML)),
+    );
     engine.set_property(
         "AppStyleQML".into(),
         QVariant::from(QString::from(qml_resources::APP_STYLE_QML)),
     );
 
+    // Where the WebEngine profiles keep their storage and cache
+    let web_profile_root = paths::profiles_dir()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_default();
+    engine.set_property(
+        "webProfileRoot".into(),
+        QVariant::from(QString::from(web_profile_root)),
+    );
+    engine.set_property(
+        "webCacheRoot".into(),
+        QVariant::from(QString::from(
+            paths::web_cache_dir().to_string_lossy().to_string(),
+        )),
+    );
+
     // Expose application directory path
     let app_dir = std::env::current_exe()
         .unwrap()
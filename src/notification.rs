@@ -1,13 +1,80 @@
+use chrono::Local;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::digest::DigestSettings;
+use crate::notification_hook::CommandHook;
+use crate::notification_server::NotificationCapabilities;
+use crate::push::PushTarget;
+use crate::speech::{Announcer, SpeechSettings};
+
+/// Longest a ringtone loops, WhatsApp gives up on unanswered calls sooner.
+const RING_TIMEOUT: Duration = Duration::from_secs(90);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NotificationSettings {
+    #[serde(default = "default_true")]
     pub enabled: bool,
+    #[serde(default = "default_true")]
     pub show_message_notifications: bool,
+    #[serde(default = "default_true")]
     pub show_call_notifications: bool,
+    #[serde(default = "default_true")]
     pub sound_enabled: bool,
+    #[serde(default = "default_true")]
     pub show_sender: bool,
+    #[serde(default)]
+    pub do_not_disturb: bool,
+    #[serde(default)]
+    pub rules: Vec<NotificationRule>,
+    /// Maximum message notifications per account and minute, 0 = unlimited.
+    #[serde(default = "default_rate_limit")]
+    pub rate_limit_per_minute: u32,
+    #[serde(default = "default_history_limit")]
+    pub history_limit: usize,
+    #[serde(default = "default_history_retention_days")]
+    pub history_retention_days: u32,
+    #[serde(default = "default_avatar_cache_limit_mb")]
+    pub avatar_cache_limit_mb: u32,
+    #[serde(default = "default_avatar_max_age_days")]
+    pub avatar_max_age_days: u32,
+    #[serde(default)]
+    pub command_hook: CommandHook,
+    #[serde(default)]
+    pub push_targets: Vec<PushTarget>,
+    #[serde(default)]
+    pub digest: DigestSettings,
+    #[serde(default)]
+    pub speech: SpeechSettings,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_rate_limit() -> u32 {
+    10
+}
+
+fn default_history_limit() -> usize {
+    500
+}
+
+fn default_history_retention_days() -> u32 {
+    30
+}
+
+fn default_avatar_cache_limit_mb() -> u32 {
+    20
+}
+
+fn default_avatar_max_age_days() -> u32 {
+    14
 }
 
 impl Default for NotificationSettings {
@@ -18,6 +85,86 @@ impl Default for NotificationSettings {
             show_call_notifications: true,
             sound_enabled: true,
             show_sender: true,
+            do_not_disturb: false,
+            rules: Vec::new(),
+            rate_limit_per_minute: default_rate_limit(),
+            history_limit: default_history_limit(),
+            history_retention_days: default_history_retention_days(),
+            avatar_cache_limit_mb: default_avatar_cache_limit_mb(),
+            avatar_max_age_days: default_avatar_max_age_days(),
+            command_hook: CommandHook::default(),
+            push_targets: Vec::new(),
+            digest: DigestSettings::default(),
+            speech: SpeechSettings::default(),
+        }
+    }
+}
+
+/// Matches notifications by account and sender. Empty fields match anything,
+/// `sender` is a case-insensitive substring match. The first matching rule wins.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotificationRule {
+    #[serde(default)]
+    pub account: String,
+    #[serde(default)]
+    pub sender: String,
+    #[serde(default)]
+    pub mute: bool,
+    /// Read matching messages aloud when speech announcements are enabled.
+    #[serde(default)]
+    pub speak: bool,
+}
+
+impl NotificationRule {
+    pub fn matches(&self, account: &str, sender: &str) -> bool {
+        (self.account.is_empty() || self.account == account)
+            && (self.sender.is_empty()
+                || sender.to_lowercase().contains(&self.sender.to_lowercase()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuppressionReason {
+    Disabled,
+    DoNotDisturb,
+    Rule,
+    RateLimit,
+}
+
+impl SuppressionReason {
+    /// Stable identifier, as serialized in the history and passed to hooks.
+    pub fn key(&self) -> &'static str {
+        match self {
+            SuppressionReason::Disabled => "disabled",
+            SuppressionReason::DoNotDisturb => "do_not_disturb",
+            SuppressionReason::Rule => "rule",
+            SuppressionReason::RateLimit => "rate_limit",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SuppressionReason::Disabled => "Notifications disabled",
+            SuppressionReason::DoNotDisturb => "Do not disturb",
+            SuppressionReason::Rule => "Muted by rule",
+            SuppressionReason::RateLimit => "Rate limited",
+        }
+    }
+}
+
+/// Outcome of a notification request, recorded in the notification history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Shown,
+    Suppressed(SuppressionReason),
+}
+
+impl Delivery {
+    pub fn suppression(&self) -> Option<SuppressionReason> {
+        match self {
+            Delivery::Shown => None,
+            Delivery::Suppressed(reason) => Some(*reason),
         }
     }
 }
@@ -40,21 +187,244 @@ impl fmt::Display for NotificationError {
 }
 impl std::error::Error for NotificationError {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallKind {
+    Voice,
+    Video,
+}
+
+/// Exact texts of WhatsApp Web's call notifications, anything else is a
+/// message even when it talks about calls.
+const CALL_TEXTS: &[(&str, CallKind)] = &[
+    ("incoming voice call", CallKind::Voice),
+    ("incoming video call", CallKind::Video),
+];
+
+impl CallKind {
+    /// Recognises the title or body of a call notification, "Incoming voice
+    /// call" or "Incoming video call".
+    pub fn detect(text: &str) -> Option<Self> {
+        let text = text.trim().to_lowercase();
+        CALL_TEXTS
+            .iter()
+            .find(|(call_text, _)| text == *call_text)
+            .map(|(_, kind)| *kind)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CallKind::Voice => "voice",
+            CallKind::Video => "video",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallAction {
+    Answer,
+    Decline,
+    Dismissed,
+}
+
+#[derive(Debug, Clone)]
+pub struct IncomingCall {
+    pub tab_index: i32,
+    pub account: String,
+    pub caller: String,
+    pub kind: CallKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationEventKind {
+    Message,
+    Call,
+}
+
+impl NotificationEventKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            NotificationEventKind::Message => "message",
+            NotificationEventKind::Call => "call",
+        }
+    }
+}
+
+/// An incoming message or call, as handed to hooks and push targets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationEvent {
+    pub event: NotificationEventKind,
+    pub account: String,
+    pub tab_index: i32,
+    pub sender: String,
+    pub chat: String,
+    pub text: String,
+    pub call_kind: Option<CallKind>,
+    pub suppressed: Option<SuppressionReason>,
+    pub timestamp: i64,
+}
+
+impl NotificationEvent {
+    pub fn message(
+        tab_index: i32,
+        account: &str,
+        chat: &str,
+        body: &str,
+        suppressed: Option<SuppressionReason>,
+    ) -> Self {
+        let (sender, text) = split_group_sender(chat, body);
+        Self {
+            event: NotificationEventKind::Message,
+            account: account.to_string(),
+            tab_index,
+            sender: sender.to_string(),
+            chat: chat.to_string(),
+            text: text.to_string(),
+            call_kind: None,
+            suppressed,
+            timestamp: Local::now().timestamp(),
+        }
+    }
+
+    pub fn call(
+        tab_index: i32,
+        account: &str,
+        caller: &str,
+        kind: CallKind,
+        suppressed: Option<SuppressionReason>,
+    ) -> Self {
+        Self {
+            event: NotificationEventKind::Call,
+            account: account.to_string(),
+            tab_index,
+            sender: caller.to_string(),
+            chat: caller.to_string(),
+            text: String::new(),
+            call_kind: Some(kind),
+            suppressed,
+            timestamp: Local::now().timestamp(),
+        }
+    }
+}
+
+/// WhatsApp Web titles group notifications with the group name and prefixes
+/// the body with "Sender: ". Returns `(sender, text)`.
+pub fn split_group_sender<'a>(chat: &'a str, body: &'a str) -> (&'a str, &'a str) {
+    match body.split_once(": ") {
+        Some((sender, text))
+            if !sender.is_empty() && sender.chars().count() <= 40 && !sender.contains('\n') =>
+        {
+            (sender, text)
+        }
+        _ => (chat, body),
+    }
+}
+
+struct RingingCall {
+    notification_id: Option<u32>,
+    ringtone_stop: Arc<AtomicBool>,
+}
+
 pub struct NotificationService {
     settings: NotificationSettings,
+    capabilities: NotificationCapabilities,
+    ringing: HashMap<i32, RingingCall>,
+    recent: HashMap<String, VecDeque<Instant>>,
+    announcer: Announcer,
 }
 
 impl NotificationService {
     pub fn new() -> Self {
         Self {
             settings: NotificationSettings::default(),
+            capabilities: NotificationCapabilities::default(),
+            ringing: HashMap::new(),
+            recent: HashMap::new(),
+            announcer: Announcer::default(),
         }
     }
 
+    pub fn settings(&self) -> &NotificationSettings {
+        &self.settings
+    }
+
     pub fn update_settings(&mut self, settings: NotificationSettings) {
         self.settings = settings;
     }
 
+    pub fn capabilities(&self) -> &NotificationCapabilities {
+        &self.capabilities
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: NotificationCapabilities) {
+        println!(
+            "Notification server: {} supports {}",
+            capabilities.server_label(),
+            capabilities.summary()
+        );
+        self.capabilities = capabilities;
+    }
+
+    fn check_suppression(&self, account: &str, sender: &str) -> Option<SuppressionReason> {
+        if !self.settings.enabled {
+            return Some(SuppressionReason::Disabled);
+        }
+        if self.settings.do_not_disturb {
+            return Some(SuppressionReason::DoNotDisturb);
+        }
+        match self.rule_for(account, sender) {
+            Some(rule) if rule.mute => Some(SuppressionReason::Rule),
+            _ => None,
+        }
+    }
+
+    fn rule_for(&self, account: &str, sender: &str) -> Option<&NotificationRule> {
+        self.settings
+            .rules
+            .iter()
+            .find(|rule| rule.matches(account, sender))
+    }
+
+    /// Reads a delivered message aloud if speech is enabled for the chat.
+    fn announce(&mut self, account: &str, chat: &str, message: &str) {
+        let speech = &self.settings.speech;
+        let opted_in =
+            speech.announce_all || self.rule_for(account, chat).is_some_and(|rule| rule.speak);
+        if !speech.enabled || !opted_in {
+            return;
+        }
+        let (sender, text) = split_group_sender(chat, message);
+        if self.settings.show_sender {
+            self.announcer.announce(speech, sender, chat, text);
+        } else {
+            self.announcer.announce(speech, account, account, text);
+        }
+    }
+
+    /// Records a message for `account` and reports whether it exceeds the
+    /// per-minute limit.
+    fn rate_limited(&mut self, account: &str) -> bool {
+        let limit = self.settings.rate_limit_per_minute as usize;
+        if limit == 0 {
+            return false;
+        }
+
+        let now = Instant::now();
+        let recent = self.recent.entry(account.to_string()).or_default();
+        while recent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) > Duration::from_secs(60))
+        {
+            recent.pop_front();
+        }
+        if recent.len() >= limit {
+            return true;
+        }
+        recent.push_back(now);
+        false
+    }
+
     pub fn send_notification(
         &self,
         title: &str,
@@ -95,18 +465,28 @@ impl NotificationService {
     ) -> Result<(), NotificationError> {
         use notify_rust::Notification;
 
+        let body = if self.capabilities.body_markup {
+            escape_markup(message)
+        } else {
+            message.to_string()
+        };
+
         let mut notification = Notification::new();
         notification
             .summary(title)
-            .body(message)
+            .body(&body)
             .appname("WhatsApp-QT")
             .timeout(notify_rust::Timeout::Milliseconds(5000));
-        if let Some(icon) = icon_path {
+        if let (Some(icon), true) = (icon_path, self.capabilities.icons) {
             notification.icon(icon);
         }
 
         if self.settings.sound_enabled {
-            notification.sound_name("message-new-instant");
+            if self.capabilities.sound {
+                notification.sound_name("message-new-instant");
+            } else {
+                play_sound("message-new-instant");
+            }
         }
 
         notification
@@ -165,16 +545,277 @@ impl NotificationService {
         }
     }
 
-    pub fn test_notification(&self) -> Result<(), NotificationError> {
-         let icon_path = std::env::current_dir()
-            .unwrap()
-            .join("resources/icons/tray.png")
-            .to_string_lossy()
-            .to_string();
+    pub fn notify_message(
+        &mut self,
+        account: &str,
+        sender: &str,
+        message: &str,
+        icon_path: Option<&str>,
+    ) -> Result<Delivery, NotificationError> {
+        if !self.settings.show_message_notifications {
+            return Ok(Delivery::Suppressed(SuppressionReason::Disabled));
+        }
+        if let Some(reason) = self.check_suppression(account, sender) {
+            return Ok(Delivery::Suppressed(reason));
+        }
+        if self.rate_limited(account) {
+            return Ok(Delivery::Suppressed(SuppressionReason::RateLimit));
+        }
+        self.announce(account, sender, message);
+
+        let title = if self.settings.show_sender && !sender.is_empty() {
+            format!("{} ({})", sender, account)
+        } else {
+            account.to_string()
+        };
+        self.send_notification(&title, message, icon_path)?;
+        Ok(Delivery::Shown)
+    }
+
+    pub fn is_ringing(&self, tab_index: i32) -> bool {
+        self.ringing.contains_key(&tab_index)
+    }
+
+    pub fn has_ringing_calls(&self) -> bool {
+        !self.ringing.is_empty()
+    }
+
+    /// Shows a persistent call notification for `call` and loops the ringtone
+    /// until the call is answered, declined, dismissed or ends in the page.
+    /// `on_action` is invoked from a background thread.
+    pub fn ring<F>(
+        &mut self,
+        call: IncomingCall,
+        icon_path: Option<&str>,
+        on_action: F,
+    ) -> Result<Delivery, NotificationError>
+    where
+        F: Fn(i32, CallAction) + Send + 'static,
+    {
+        if !self.settings.show_call_notifications {
+            return Ok(Delivery::Suppressed(SuppressionReason::Disabled));
+        }
+        if let Some(reason) = self.check_suppression(&call.account, &call.caller) {
+            return Ok(Delivery::Suppressed(reason));
+        }
+        if self.is_ringing(call.tab_index) {
+            return Ok(Delivery::Shown);
+        }
+
+        let ringtone_stop = Arc::new(AtomicBool::new(false));
+        if self.settings.sound_enabled {
+            spawn_ringtone(Arc::clone(&ringtone_stop));
+        }
+
+        match self.send_call_notification(&call, icon_path, Arc::clone(&ringtone_stop), on_action) {
+            Ok(notification_id) => {
+                self.ringing.insert(
+                    call.tab_index,
+                    RingingCall {
+                        notification_id,
+                        ringtone_stop,
+                    },
+                );
+                Ok(Delivery::Shown)
+            }
+            Err(e) => {
+                ringtone_stop.store(true, Ordering::Relaxed);
+                Err(e)
+            }
+        }
+    }
+
+    /// Stops the ringtone and removes the call notification for a tab.
+    pub fn dismiss_call(&mut self, tab_index: i32) {
+        if let Some(call) = self.ringing.remove(&tab_index) {
+            call.ringtone_stop.store(true, Ordering::Relaxed);
+            if let Some(id) = call.notification_id {
+                close_notification(id);
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn send_call_notification<F>(
+        &self,
+        call: &IncomingCall,
+        icon_path: Option<&str>,
+        ringtone_stop: Arc<AtomicBool>,
+        on_action: F,
+    ) -> Result<Option<u32>, NotificationError>
+    where
+        F: Fn(i32, CallAction) + Send + 'static,
+    {
+        use notify_rust::{Hint, Notification, Timeout, Urgency};
+
+        let caller = if self.settings.show_sender && !call.caller.is_empty() {
+            call.caller.as_str()
+        } else {
+            "Unknown caller"
+        };
+
+        let mut notification = Notification::new();
+        notification
+            .summary(&format!("Incoming {} call", call.kind.label()))
+            .body(&format!("{} ({})", caller, call.account))
+            .appname("WhatsApp-QT")
+            .urgency(Urgency::Critical)
+            .hint(Hint::Category("call.incoming".to_string()))
+            .timeout(Timeout::Never);
+        if self.capabilities.persistence {
+            notification.hint(Hint::Resident(true));
+        }
+        if self.capabilities.actions {
+            notification
+                .action("answer", "Answer")
+                .action("decline", "Decline");
+        }
+        if let (Some(icon), true) = (icon_path, self.capabilities.icons) {
+            notification.icon(icon);
+        }
+
+        let handle = notification
+            .show()
+            .map_err(|e| NotificationError::SendFailed(e.to_string()))?;
+        let notification_id = handle.id();
+        let tab_index = call.tab_index;
+
+        thread::spawn(move || {
+            handle.wait_for_action(|action| {
+                ringtone_stop.store(true, Ordering::Relaxed);
+                let action = match action {
+                    "answer" => CallAction::Answer,
+                    "decline" => CallAction::Decline,
+                    _ => CallAction::Dismissed,
+                };
+                on_action(tab_index, action);
+            });
+        });
+
+        Ok(Some(notification_id))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn send_call_notification<F>(
+        &self,
+        call: &IncomingCall,
+        icon_path: Option<&str>,
+        _ringtone_stop: Arc<AtomicBool>,
+        _on_action: F,
+    ) -> Result<Option<u32>, NotificationError>
+    where
+        F: Fn(i32, CallAction) + Send + 'static,
+    {
+        self.send_notification(
+            &format!("Incoming {} call", call.kind.label()),
+            &format!("{} ({})", call.caller, call.account),
+            icon_path,
+        )?;
+        Ok(None)
+    }
+
+    /// Sends a test notification and returns a description of what the
+    /// notification server supports.
+    pub fn test_notification(&self) -> Result<String, NotificationError> {
+        let icon_path = tray_icon_path();
+        let report = format!(
+            "Server: {}\nSupports: {}",
+            self.capabilities.server_label(),
+            self.capabilities.summary()
+        );
         self.send_notification(
             "WhatsApp-QT",
-            "Test Message from WhatsApp-QT",
-            Some(&icon_path),
+            &format!("Test Message from WhatsApp-QT\n{}", report),
+            icon_path.as_deref(),
+        )?;
+        Ok(report)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Plays a freedesktop sound theme event ourselves, for servers without
+/// the "sound" capability.
+#[cfg(target_os = "linux")]
+fn play_sound(event_id: &'static str) {
+    thread::spawn(move || {
+        let _ = std::process::Command::new("canberra-gtk-play")
+            .args(["--id", event_id])
+            .status();
+    });
+}
+
+/// The application icon, installed next to the executable.
+pub fn tray_icon_path() -> Option<String> {
+    let exe = std::env::current_exe().ok()?;
+    let path = exe.parent()?.join("resources/icons/tray.png");
+    path.exists().then(|| path.to_string_lossy().to_string())
+}
+
+/// Loops the freedesktop "phone-incoming-call" sound until `stop` is set or
+/// `RING_TIMEOUT` has passed, whichever comes first.
+fn spawn_ringtone(stop: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let started = Instant::now();
+        while !stop.load(Ordering::Relaxed) && started.elapsed() < RING_TIMEOUT {
+            let played = std::process::Command::new("canberra-gtk-play")
+                .args(["--id", "phone-incoming-call"])
+                .status();
+            if played.is_err() {
+                println!("canberra-gtk-play not available, ringtone disabled");
+                break;
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+fn close_notification(id: u32) {
+    let result = dbus::blocking::Connection::new_session().and_then(|conn| {
+        let proxy = conn.with_proxy(
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            Duration::from_millis(500),
+        );
+        proxy.method_call::<(), _, _, _>(
+            "org.freedesktop.Notifications",
+            "CloseNotification",
+            (id,),
         )
+    });
+    if let Err(e) = result {
+        println!("Failed to close notification {}: {}", id, e);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn close_notification(_id: u32) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_only_the_exact_call_texts() {
+        assert_eq!(
+            CallKind::detect("Incoming voice call"),
+            Some(CallKind::Voice)
+        );
+        assert_eq!(
+            CallKind::detect("  INCOMING VIDEO CALL\n"),
+            Some(CallKind::Video)
+        );
+        assert_eq!(CallKind::detect("Missed voice call"), None);
+        assert_eq!(
+            CallKind::detect("Can you take the incoming voice call?"),
+            None
+        );
+        assert_eq!(CallKind::detect(""), None);
     }
 }
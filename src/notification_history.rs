@@ -0,0 +1,320 @@
+use chrono::{Local, TimeZone};
+use qmetaobject::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::json_writer::JsonWriter;
+use crate::notification::SuppressionReason;
+
+pub const HISTORY_FILE: &str = "notification_history.json";
+const PREVIEW_LEN: usize = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryKind {
+    Message,
+    Call,
+}
+
+impl HistoryKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HistoryKind::Message => "message",
+            HistoryKind::Call => "call",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// Unix timestamp in seconds.
+    pub timestamp: i64,
+    pub kind: HistoryKind,
+    /// Tab position when the entry was recorded, see `storage`.
+    pub tab_index: i32,
+    /// Web profile of the account, which still finds it after tabs moved.
+    /// Empty in entries from before it was stored.
+    #[serde(default)]
+    pub storage: String,
+    pub account: String,
+    pub sender: String,
+    pub preview: String,
+    pub suppressed: Option<SuppressionReason>,
+    #[serde(default)]
+    pub read: bool,
+}
+
+impl HistoryEntry {
+    pub fn new(
+        kind: HistoryKind,
+        tab_index: i32,
+        storage: String,
+        account: String,
+        sender: String,
+        text: &str,
+        suppressed: Option<SuppressionReason>,
+    ) -> Self {
+        Self {
+            id: 0,
+            timestamp: Local::now().timestamp(),
+            kind,
+            tab_index,
+            storage,
+            account,
+            sender,
+            preview: text.chars().take(PREVIEW_LEN).collect(),
+            suppressed,
+            read: false,
+        }
+    }
+
+    pub fn time_label(&self) -> String {
+        Local
+            .timestamp_opt(self.timestamp, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Notification history persisted as JSON in the data directory,
+/// newest entries first.
+pub struct NotificationHistory {
+    entries: VecDeque<HistoryEntry>,
+    next_id: u64,
+    limit: usize,
+    retention_days: u32,
+    writer: Option<JsonWriter<VecDeque<HistoryEntry>>>,
+}
+
+impl Default for NotificationHistory {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            next_id: 1,
+            limit: 500,
+            retention_days: 30,
+            writer: None,
+        }
+    }
+}
+
+impl NotificationHistory {
+    pub fn load(&mut self, path: PathBuf) {
+        self.entries = match fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                println!("Ignoring unreadable notification history: {}", e);
+                VecDeque::new()
+            }),
+            Err(_) => VecDeque::new(),
+        };
+        self.next_id = self.entries.iter().map(|e| e.id).max().unwrap_or(0) + 1;
+        self.writer = Some(JsonWriter::start(path, "notification history"));
+        self.trim();
+    }
+
+    /// Changes the retention, the caller trims afterwards.
+    pub fn set_retention(&mut self, limit: usize, retention_days: u32) {
+        self.limit = limit;
+        self.retention_days = retention_days;
+    }
+
+    pub fn entries(&self) -> &VecDeque<HistoryEntry> {
+        &self.entries
+    }
+
+    pub fn get(&self, row: usize) -> Option<&HistoryEntry> {
+        self.entries.get(row)
+    }
+
+    pub fn get_mut(&mut self, row: usize) -> Option<&mut HistoryEntry> {
+        self.entries.get_mut(row)
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.entries.iter().filter(|e| !e.read).count()
+    }
+
+    /// Adds `entry` as the newest, the caller trims afterwards.
+    pub fn record(&mut self, mut entry: HistoryEntry) {
+        entry.id = self.next_id;
+        self.next_id += 1;
+        self.entries.push_front(entry);
+    }
+
+    pub fn mark_all_read(&mut self) {
+        self.entries.iter_mut().for_each(|e| e.read = true);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Number of leading entries within the retention limit and age. The
+    /// entries are newest first, so everything after them is dropped.
+    pub fn kept_len(&self) -> usize {
+        let cutoff = Local::now().timestamp() - i64::from(self.retention_days) * 86_400;
+        self.entries
+            .iter()
+            .take(self.limit)
+            .take_while(|e| self.retention_days == 0 || e.timestamp >= cutoff)
+            .count()
+    }
+
+    /// Drops entries beyond the retention limit or older than the retention age.
+    pub fn trim(&mut self) {
+        self.entries.truncate(self.kept_len());
+    }
+
+    /// Queues the entries for writing on the background task.
+    pub fn save(&self) {
+        if let Some(writer) = &self.writer {
+            writer.save(self.entries.clone());
+        }
+    }
+}
+
+const ROLE_KIND: i32 = USER_ROLE;
+const ROLE_ACCOUNT: i32 = USER_ROLE + 1;
+const ROLE_SENDER: i32 = USER_ROLE + 2;
+const ROLE_PREVIEW: i32 = USER_ROLE + 3;
+const ROLE_TIME: i32 = USER_ROLE + 4;
+const ROLE_REASON: i32 = USER_ROLE + 5;
+const ROLE_READ: i32 = USER_ROLE + 6;
+const ROLE_TAB_INDEX: i32 = USER_ROLE + 7;
+
+/// List model over the notification history for the notification center panel.
+#[derive(QObject, Default)]
+pub struct NotificationHistoryModel {
+    base: qt_base_class!(trait QAbstractListModel),
+    unread_count: qt_property!(i32; NOTIFY unread_count_changed),
+    unread_count_changed: qt_signal!(),
+    history: NotificationHistory,
+
+    mark_read: qt_method!(
+        fn mark_read(&mut self, row: i32) {
+            if let Some(entry) = self.history.get_mut(row as usize) {
+                if entry.read {
+                    return;
+                }
+                entry.read = true;
+            } else {
+                return;
+            }
+            let index = (self as &mut dyn QAbstractListModel).row_index(row);
+            (self as &mut dyn QAbstractListModel).data_changed(index.clone(), index);
+            self.history_changed();
+        }
+    ),
+    mark_all_read: qt_method!(
+        fn mark_all_read(&mut self) {
+            (self as &mut dyn QAbstractListModel).begin_reset_model();
+            self.history.mark_all_read();
+            (self as &mut dyn QAbstractListModel).end_reset_model();
+            self.history_changed();
+        }
+    ),
+    clear: qt_method!(
+        fn clear(&mut self) {
+            (self as &mut dyn QAbstractListModel).begin_reset_model();
+            self.history.clear();
+            (self as &mut dyn QAbstractListModel).end_reset_model();
+            self.history_changed();
+        }
+    ),
+}
+
+impl NotificationHistoryModel {
+    pub fn load(&mut self, path: PathBuf) {
+        (self as &mut dyn QAbstractListModel).begin_reset_model();
+        self.history.load(path);
+        (self as &mut dyn QAbstractListModel).end_reset_model();
+        self.update_unread_count();
+    }
+
+    /// Applies the retention from the settings, on load and whenever they
+    /// change.
+    pub fn set_retention(&mut self, limit: usize, retention_days: u32) {
+        self.history.set_retention(limit, retention_days);
+        if self.trim_rows() {
+            self.history_changed();
+        }
+    }
+
+    pub fn entry(&self, row: i32) -> Option<&HistoryEntry> {
+        self.history.get(row as usize)
+    }
+
+    pub fn record(&mut self, entry: HistoryEntry) {
+        (self as &mut dyn QAbstractListModel).begin_insert_rows(0, 0);
+        self.history.record(entry);
+        (self as &mut dyn QAbstractListModel).end_insert_rows();
+        self.trim_rows();
+        self.history_changed();
+    }
+
+    /// Removes the rows beyond the retention, returns whether there were any.
+    fn trim_rows(&mut self) -> bool {
+        let kept = self.history.kept_len() as i32;
+        let len = self.history.entries().len() as i32;
+        if kept >= len {
+            return false;
+        }
+        (self as &mut dyn QAbstractListModel).begin_remove_rows(kept, len - 1);
+        self.history.trim();
+        (self as &mut dyn QAbstractListModel).end_remove_rows();
+        true
+    }
+
+    fn history_changed(&mut self) {
+        self.history.save();
+        self.update_unread_count();
+    }
+
+    fn update_unread_count(&mut self) {
+        let unread = self.history.unread_count() as i32;
+        if unread != self.unread_count {
+            self.unread_count = unread;
+            self.unread_count_changed();
+        }
+    }
+}
+
+impl QAbstractListModel for NotificationHistoryModel {
+    fn row_count(&self) -> i32 {
+        self.history.entries().len() as i32
+    }
+
+    fn data(&self, index: QModelIndex, role: i32) -> QVariant {
+        let Some(entry) = self.history.get(index.row() as usize) else {
+            return QVariant::default();
+        };
+        match role {
+            ROLE_KIND => QString::from(entry.kind.label()).into(),
+            ROLE_ACCOUNT => QString::from(entry.account.as_str()).into(),
+            ROLE_SENDER => QString::from(entry.sender.as_str()).into(),
+            ROLE_PREVIEW => QString::from(entry.preview.as_str()).into(),
+            ROLE_TIME => QString::from(entry.time_label()).into(),
+            ROLE_REASON => QString::from(entry.suppressed.map(|r| r.label()).unwrap_or("")).into(),
+            ROLE_READ => entry.read.into(),
+            ROLE_TAB_INDEX => entry.tab_index.into(),
+            _ => QVariant::default(),
+        }
+    }
+
+    fn role_names(&self) -> HashMap<i32, QByteArray> {
+        HashMap::from([
+            (ROLE_KIND, "kind".into()),
+            (ROLE_ACCOUNT, "account".into()),
+            (ROLE_SENDER, "sender".into()),
+            (ROLE_PREVIEW, "preview".into()),
+            (ROLE_TIME, "time".into()),
+            (ROLE_REASON, "reason".into()),
+            (ROLE_READ, "read".into()),
+            (ROLE_TAB_INDEX, "tabIndex".into()),
+        ])
+    }
+}
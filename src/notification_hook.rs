@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+
+use crate::notification::NotificationEvent;
+use crate::runtime::runtime;
+
+/// User command run for every incoming message or call. The event is passed
+/// both as `WAQT_*` environment variables and as JSON on stdin.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandHook {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Replace the message text with "[redacted]" before handing it out.
+    #[serde(default)]
+    pub redact_text: bool,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: usize,
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_max_concurrent() -> usize {
+    2
+}
+
+impl Default for CommandHook {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            command: String::new(),
+            args: Vec::new(),
+            redact_text: false,
+            timeout_secs: default_timeout_secs(),
+            max_concurrent: default_max_concurrent(),
+        }
+    }
+}
+
+pub struct HookRunner {
+    hook: CommandHook,
+    slots: Arc<Semaphore>,
+}
+
+impl HookRunner {
+    pub fn new() -> Self {
+        let hook = CommandHook::default();
+        Self {
+            slots: Arc::new(Semaphore::new(hook.max_concurrent.max(1))),
+            hook,
+        }
+    }
+
+    pub fn configure(&mut self, hook: CommandHook) {
+        if hook.max_concurrent != self.hook.max_concurrent {
+            self.slots = Arc::new(Semaphore::new(hook.max_concurrent.max(1)));
+        }
+        self.hook = hook;
+    }
+
+    /// Runs the hook in the background. Events wait for a free slot when
+    /// `max_concurrent` commands are already running.
+    pub fn run(&self, mut event: NotificationEvent) {
+        if !self.hook.enabled || self.hook.command.trim().is_empty() {
+            return;
+        }
+        if self.hook.redact_text && !event.text.is_empty() {
+            event.text = "[redacted]".to_string();
+        }
+
+        let hook = self.hook.clone();
+        let slots = Arc::clone(&self.slots);
+        runtime().spawn(async move {
+            let Ok(_slot) = slots.acquire_owned().await else {
+                return;
+            };
+            if let Err(e) = run_command(&hook, &event).await {
+                println!("Notification hook '{}' failed: {}", hook.command, e);
+            }
+        });
+    }
+}
+
+async fn run_command(
+    hook: &CommandHook,
+    event: &NotificationEvent,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut child = tokio::process::Command::new(&hook.command)
+        .args(&hook.args)
+        .envs(hook_env(event))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let json = serde_json::to_vec(event)?;
+        // The command may ignore stdin and exit early, that is fine.
+        let _ = stdin.write_all(&json).await;
+    }
+
+    let timeout = Duration::from_secs(hook.timeout_secs.max(1));
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => {
+            let status = status?;
+            if !status.success() {
+                return Err(format!("exited with {}", status).into());
+            }
+            Ok(())
+        }
+        Err(_) => {
+            let _ = child.kill().await;
+            Err(format!("timed out after {}s", timeout.as_secs()).into())
+        }
+    }
+}
+
+fn hook_env(event: &NotificationEvent) -> Vec<(&'static str, String)> {
+    vec![
+        ("WAQT_EVENT", event.event.label().to_string()),
+        ("WAQT_ACCOUNT", event.account.clone()),
+        ("WAQT_TAB", event.tab_index.to_string()),
+        ("WAQT_SENDER", event.sender.clone()),
+        ("WAQT_CHAT", event.chat.clone()),
+        ("WAQT_TEXT", event.text.clone()),
+        (
+            "WAQT_CALL_KIND",
+            event.call_kind.map(|k| k.label()).unwrap_or("").to_string(),
+        ),
+        (
+            "WAQT_SUPPRESSED",
+            event.suppressed.map(|r| r.key()).unwrap_or("").to_string(),
+        ),
+        ("WAQT_TIMESTAMP", event.timestamp.to_string()),
+    ]
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+const NOTIFICATIONS_NAME: &str = "org.freedesktop.Notifications";
+#[cfg(target_os = "linux")]
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+#[derive(Debug, Clone, Default)]
+pub struct ServerInfo {
+    pub name: String,
+    pub vendor: String,
+    pub version: String,
+    pub spec_version: String,
+}
+
+impl fmt::Display for ServerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({}, spec {})",
+            self.name, self.version, self.vendor, self.spec_version
+        )
+    }
+}
+
+/// What the running notification server supports, as reported by
+/// `GetCapabilities` and `GetServerInformation`.
+#[derive(Debug, Clone)]
+pub struct NotificationCapabilities {
+    pub actions: bool,
+    pub body_markup: bool,
+    pub sound: bool,
+    pub icons: bool,
+    pub persistence: bool,
+    pub server: Option<ServerInfo>,
+}
+
+impl Default for NotificationCapabilities {
+    /// Used until detection has run, or when there is no notification server:
+    /// assume the common feature set so behaviour matches older releases.
+    fn default() -> Self {
+        Self {
+            actions: true,
+            body_markup: false,
+            sound: true,
+            icons: true,
+            persistence: true,
+            server: None,
+        }
+    }
+}
+
+impl NotificationCapabilities {
+    pub fn from_capabilities(raw: Vec<String>, server: Option<ServerInfo>) -> Self {
+        let has = |cap: &str| raw.iter().any(|c| c == cap);
+        Self {
+            actions: has("actions"),
+            body_markup: has("body-markup"),
+            sound: has("sound"),
+            icons: has("icon-static") || has("icon-multi"),
+            persistence: has("persistence"),
+            server,
+        }
+    }
+
+    /// Human readable list of the features NotificationService makes use of.
+    pub fn summary(&self) -> String {
+        let features = [
+            ("actions", self.actions),
+            ("body markup", self.body_markup),
+            ("sounds", self.sound),
+            ("icons", self.icons),
+            ("persistence", self.persistence),
+        ];
+        let supported: Vec<&str> = features
+            .iter()
+            .filter(|(_, supported)| *supported)
+            .map(|(name, _)| *name)
+            .collect();
+        if supported.is_empty() {
+            "none".to_string()
+        } else {
+            supported.join(", ")
+        }
+    }
+
+    pub fn server_label(&self) -> String {
+        match &self.server {
+            Some(server) => server.to_string(),
+            None => "Not detected".to_string(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn detect() -> Result<NotificationCapabilities, dbus::Error> {
+    let conn = dbus::blocking::Connection::new_session()?;
+    let proxy = conn.with_proxy(
+        NOTIFICATIONS_NAME,
+        NOTIFICATIONS_PATH,
+        Duration::from_millis(2000),
+    );
+
+    let (raw,): (Vec<String>,) = proxy.method_call(NOTIFICATIONS_NAME, "GetCapabilities", ())?;
+    let server = proxy
+        .method_call::<(String, String, String, String), _, _, _>(
+            NOTIFICATIONS_NAME,
+            "GetServerInformation",
+            (),
+        )
+        .map(|(name, vendor, version, spec_version)| ServerInfo {
+            name,
+            vendor,
+            version,
+            spec_version,
+        })
+        .ok();
+
+    Ok(NotificationCapabilities::from_capabilities(raw, server))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn detect() -> Result<NotificationCapabilities, String> {
+    Ok(NotificationCapabilities::default())
+}
+
+/// Re-runs detection whenever a notification server takes over the bus name,
+/// e.g. after plasmashell or the notification daemon restarts.
+#[cfg(target_os = "linux")]
+pub fn watch_server_restarts<F>(on_change: F)
+where
+    F: Fn(NotificationCapabilities) + Send + 'static,
+{
+    use dbus::message::MatchRule;
+
+    std::thread::spawn(move || {
+        let conn = match dbus::blocking::Connection::new_session() {
+            Ok(conn) => conn,
+            Err(e) => {
+                println!("Cannot watch notification server: {}", e);
+                return;
+            }
+        };
+
+        let rule = MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged")
+            .with_sender("org.freedesktop.DBus");
+        let added = conn.add_match(
+            rule,
+            move |(name, _old_owner, new_owner): (String, String, String), _, _| {
+                if name == NOTIFICATIONS_NAME && !new_owner.is_empty() {
+                    match detect() {
+                        Ok(capabilities) => on_change(capabilities),
+                        Err(e) => println!("Failed to query notification server: {}", e),
+                    }
+                }
+                true
+            },
+        );
+        if let Err(e) = added {
+            println!("Cannot watch notification server: {}", e);
+            return;
+        }
+
+        loop {
+            if let Err(e) = conn.process(Duration::from_millis(1000)) {
+                println!("Notification server watch stopped: {}", e);
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch_server_restarts<F>(_on_change: F)
+where
+    F: Fn(NotificationCapabilities) + Send + 'static,
+{
+}
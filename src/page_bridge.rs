@@ -0,0 +1,40 @@
+use serde::Deserialize;
+
+use crate::call_log::CallOutcome;
+use crate::notification::CallKind;
+
+/// Prefix used by `page_bridge.js` to mark console messages meant for us.
+pub const EVENT_PREFIX: &str = "waqt:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallState {
+    Ringing,
+    Ended,
+}
+
+/// Events reported by the injected page script, one JSON object per message.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PageEvent {
+    Call {
+        state: CallState,
+        kind: CallKind,
+        #[serde(default)]
+        caller: String,
+        /// Set on "ended" when the user answered or declined in the page.
+        #[serde(default)]
+        outcome: Option<CallOutcome>,
+    },
+    Avatar {
+        chat: String,
+        url: String,
+    },
+}
+
+impl PageEvent {
+    pub fn parse(message: &str) -> Result<Self, serde_json::Error> {
+        let payload = message.strip_prefix(EVENT_PREFIX).unwrap_or(message);
+        serde_json::from_str(payload)
+    }
+}
@@ -0,0 +1,328 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Directory name used under each XDG base directory.
+pub const APP_DIR: &str = "WhatsApp-QT";
+/// Settings directory of releases before the XDG layout.
+const LEGACY_CONFIG_DIR: &str = "WhatsAppDesktop";
+/// Web profiles of releases before the XDG layout, under the data directory.
+const LEGACY_PROFILE_DIR: &str = "WhatsApp-QT-Profile";
+const MIGRATION_MARKER: &str = "migration.json";
+const MIGRATION_LOG: &str = "migration.log";
+/// Environment variable that keeps everything under one directory.
+pub const HOME_ENV: &str = "WHATSAPP_QT_HOME";
+/// File next to the executable that turns on portable mode.
+pub const PORTABLE_MARKER: &str = "portable";
+/// Directory next to the executable used in portable mode.
+const PORTABLE_HOME: &str = "WhatsApp-QT-Data";
+
+static HOME: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Picks the single home directory, if any: `--config-dir`, then
+/// `$WHATSAPP_QT_HOME`, then portable mode. Without one the XDG
+/// directories are used.
+pub fn init(config_dir_arg: Option<PathBuf>) {
+    let home = config_dir_arg
+        .or_else(|| {
+            std::env::var_os(HOME_ENV)
+                .filter(|home| !home.is_empty())
+                .map(PathBuf::from)
+        })
+        .or_else(portable_home)
+        .map(|home| std::path::absolute(&home).unwrap_or(home));
+    if let Some(home) = &home {
+        println!("Keeping all data in {}", home.display());
+    }
+    if HOME.set(home).is_err() {
+        println!("Data directories were already initialized");
+    }
+}
+
+fn portable_home() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let exe_dir = exe.parent()?;
+    exe_dir
+        .join(PORTABLE_MARKER)
+        .exists()
+        .then(|| exe_dir.join(PORTABLE_HOME))
+}
+
+pub fn home() -> Option<&'static Path> {
+    HOME.get().and_then(|home| home.as_deref())
+}
+
+/// `<home>/<sub>` when a home directory is set, else `<xdg>/WhatsApp-QT`.
+fn app_dir(xdg: Option<PathBuf>, sub: &str) -> Option<PathBuf> {
+    let path = match home() {
+        Some(home) => home.join(sub),
+        None => xdg?.join(APP_DIR),
+    };
+    if let Err(e) = fs::create_dir_all(&path) {
+        println!("Failed to create {}: {}", path.display(), e);
+    }
+    Some(path)
+}
+
+/// `path` relative to the home directory when it lies inside it, so a
+/// portable copy keeps its folders after moving to another drive or mount.
+pub fn relative_to_home(path: &str) -> String {
+    home()
+        .and_then(|home| Path::new(path).strip_prefix(home).ok())
+        .filter(|relative| !relative.as_os_str().is_empty())
+        .map_or_else(
+            || path.to_string(),
+            |relative| relative.to_string_lossy().to_string(),
+        )
+}
+
+/// Resolves a path stored by [`relative_to_home`].
+pub fn resolve_in_home(path: &str) -> String {
+    match home() {
+        Some(home) if !path.is_empty() && Path::new(path).is_relative() => {
+            home.join(path).to_string_lossy().to_string()
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/WhatsApp-QT`: settings and their backups.
+pub fn config_dir() -> Option<PathBuf> {
+    app_dir(dirs::config_dir(), "config")
+}
+
+/// `$XDG_DATA_HOME/WhatsApp-QT`: web profiles, history, call log, queues.
+pub fn data_dir() -> Option<PathBuf> {
+    app_dir(dirs::data_dir(), "data")
+}
+
+/// `$XDG_CACHE_HOME/WhatsApp-QT`: avatars, tab icons and the web cache.
+pub fn cache_dir() -> PathBuf {
+    app_dir(dirs::cache_dir(), "cache").unwrap_or_else(|| std::env::temp_dir().join(APP_DIR))
+}
+
+/// Default download folder, kept inside the home directory when one is set.
+pub fn download_dir() -> PathBuf {
+    match home() {
+        Some(home) => {
+            let downloads = home.join("downloads");
+            if let Err(e) = fs::create_dir_all(&downloads) {
+                println!("Failed to create {}: {}", downloads.display(), e);
+            }
+            downloads
+        }
+        None => dirs::download_dir().unwrap_or_else(|| std::env::current_dir().unwrap()),
+    }
+}
+
+/// Parent of the per-account `whatsapp_tab_<n>` web profiles.
+pub fn profiles_dir() -> Option<PathBuf> {
+    data_dir().map(|data| data.join("profiles"))
+}
+
+pub fn web_cache_dir() -> PathBuf {
+    cache_dir().join("web")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Move {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+/// Written once the legacy directories were migrated; lists every move so
+/// `--rollback-migration` can undo them.
+#[derive(Debug, Serialize, Deserialize)]
+struct MigrationMarker {
+    migrated_at: String,
+    moves: Vec<Move>,
+}
+
+/// Moves settings from `~/.config/WhatsAppDesktop` and web profiles from
+/// `<data>/WhatsApp-QT-Profile` into the XDG layout. Runs once, later runs
+/// see the marker and return immediately.
+pub fn migrate_legacy() {
+    // A separate home starts out empty on purpose.
+    if home().is_some() {
+        return;
+    }
+    let (Some(config), Some(data)) = (config_dir(), data_dir()) else {
+        return;
+    };
+    if config.join(MIGRATION_MARKER).exists() {
+        return;
+    }
+
+    let mut planned = Vec::new();
+    if let Some(legacy) = dirs::config_dir().map(|c| c.join(LEGACY_CONFIG_DIR)) {
+        for entry in read_dir(&legacy) {
+            let name = entry.file_name();
+            let target = if name.to_string_lossy().starts_with("settings.json") {
+                &config
+            } else {
+                &data
+            };
+            planned.push(Move {
+                from: entry.path(),
+                to: target.join(name),
+            });
+        }
+    }
+    if let Some(profiles) = profiles_dir() {
+        for entry in read_dir(&data.join(LEGACY_PROFILE_DIR)) {
+            planned.push(Move {
+                from: entry.path(),
+                to: profiles.join(entry.file_name()),
+            });
+        }
+    }
+    if planned.is_empty() {
+        return;
+    }
+
+    let mut log = Vec::new();
+    let mut moves = Vec::new();
+    for planned_move in planned {
+        if planned_move.to.exists() {
+            log.push(format!(
+                "skipped {}: {} already exists",
+                planned_move.from.display(),
+                planned_move.to.display()
+            ));
+            continue;
+        }
+        match move_path(&planned_move.from, &planned_move.to) {
+            Ok(()) => {
+                log.push(format!(
+                    "moved {} -> {}",
+                    planned_move.from.display(),
+                    planned_move.to.display()
+                ));
+                moves.push(planned_move);
+            }
+            Err(e) => log.push(format!(
+                "failed to move {}: {}",
+                planned_move.from.display(),
+                e
+            )),
+        }
+    }
+
+    let marker = MigrationMarker {
+        migrated_at: Local::now().to_rfc3339(),
+        moves,
+    };
+    match serde_json::to_string_pretty(&marker) {
+        Ok(json) => {
+            if let Err(e) = fs::write(config.join(MIGRATION_MARKER), json) {
+                println!("Failed to write migration marker: {}", e);
+            }
+        }
+        Err(e) => println!("Failed to serialize migration marker: {}", e),
+    }
+    write_log(&data, "migration", &log);
+}
+
+/// Moves everything recorded in the migration marker back to the legacy
+/// directories and removes the marker.
+pub fn rollback_migration() -> Result<(), Box<dyn std::error::Error>> {
+    let (Some(config), Some(data)) = (config_dir(), data_dir()) else {
+        return Err("Could not find config directory".into());
+    };
+    let marker_path = config.join(MIGRATION_MARKER);
+    let marker: MigrationMarker = serde_json::from_str(&fs::read_to_string(&marker_path)?)?;
+
+    let mut log = Vec::new();
+    for planned_move in marker.moves.iter().rev() {
+        if planned_move.from.exists() {
+            log.push(format!(
+                "kept {}: {} exists again",
+                planned_move.to.display(),
+                planned_move.from.display()
+            ));
+            continue;
+        }
+        if let Some(parent) = planned_move.from.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match move_path(&planned_move.to, &planned_move.from) {
+            Ok(()) => log.push(format!(
+                "restored {} -> {}",
+                planned_move.to.display(),
+                planned_move.from.display()
+            )),
+            Err(e) => log.push(format!(
+                "failed to restore {}: {}",
+                planned_move.to.display(),
+                e
+            )),
+        }
+    }
+    fs::remove_file(&marker_path)?;
+    write_log(&data, "rollback", &log);
+    Ok(())
+}
+
+fn read_dir(dir: &Path) -> Vec<fs::DirEntry> {
+    fs::read_dir(dir)
+        .map(|entries| entries.filter_map(Result::ok).collect())
+        .unwrap_or_default()
+}
+
+/// Renames `from` to `to`, copying when they are on different file systems.
+pub fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            copy_recursive(from, to)?;
+            if from.is_dir() {
+                fs::remove_dir_all(from)
+            } else {
+                fs::remove_file(from)
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> std::io::Result<()> {
+    if !from.is_dir() {
+        return fs::copy(from, to).map(|_| ());
+    }
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+fn write_log(data: &Path, action: &str, lines: &[String]) {
+    for line in lines {
+        println!("Config {}: {}", action, line);
+    }
+    let entry = format!(
+        "[{}] {}\n{}\n",
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        action,
+        lines
+            .iter()
+            .map(|line| format!("  {}", line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    let result = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(data.join(MIGRATION_LOG))
+        .and_then(|mut file| file.write_all(entry.as_bytes()));
+    if let Err(e) = result {
+        println!("Failed to write {}: {}", MIGRATION_LOG, e);
+    }
+}
@@ -0,0 +1,180 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::settings::{self, AppSettings};
+
+pub const POLICY_FILE: &str = "policy.json";
+/// Policy directory name, both under `/etc` and under each XDG config dir.
+const POLICY_DIR: &str = "whatsapp-qt";
+const DEFAULT_REASON: &str = "Managed by your administrator";
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// Administrator provided settings. Keys are dotted paths into
+/// `settings.json`, e.g. `notification_settings.enabled`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Policy {
+    /// Shown next to locked settings and in rejected changes.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Used while the user's settings don't have the key.
+    #[serde(default)]
+    pub defaults: Map<String, Value>,
+    /// Always applied, the user can not change them.
+    #[serde(default)]
+    pub locked: Map<String, Value>,
+}
+
+/// The policy merged from all policy files, read on first use.
+pub fn get() -> &'static Policy {
+    POLICY.get_or_init(load)
+}
+
+/// `$XDG_CONFIG_DIRS/whatsapp-qt/policy.json`, then
+/// `/etc/whatsapp-qt/policy.json`; later files override earlier ones key by
+/// key.
+fn policy_files() -> Vec<PathBuf> {
+    let xdg = std::env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    // XDG lists the most important directory first.
+    let mut files: Vec<PathBuf> = xdg
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .rev()
+        .map(|dir| PathBuf::from(dir).join(POLICY_DIR).join(POLICY_FILE))
+        .collect();
+    files.push(PathBuf::from("/etc").join(POLICY_DIR).join(POLICY_FILE));
+    files
+}
+
+fn load() -> Policy {
+    let mut policy = Policy::default();
+    for path in policy_files() {
+        let Ok(data) = fs::read_to_string(&path) else {
+            continue;
+        };
+        match serde_json::from_str::<Policy>(&data) {
+            Ok(file) => {
+                println!("Applying policy from {}", path.display());
+                policy.reason = file.reason.or(policy.reason);
+                policy.defaults.extend(file.defaults);
+                policy.locked.extend(file.locked);
+            }
+            Err(e) => println!("Ignoring invalid policy {}: {}", path.display(), e),
+        }
+    }
+    policy.drop_invalid();
+    policy
+}
+
+impl Policy {
+    pub fn is_empty(&self) -> bool {
+        self.defaults.is_empty() && self.locked.is_empty()
+    }
+
+    /// Why `key` can't be changed, or `None` when it isn't locked. Locking
+    /// a key locks everything below it, e.g. `notification_settings` locks
+    /// `notification_settings.enabled`.
+    pub fn lock_reason(&self, key: &str) -> Option<&str> {
+        let parts = settings::key_parts(key);
+        self.locked
+            .keys()
+            .any(|locked| parts.starts_with(&settings::key_parts(locked)))
+            .then(|| self.reason.as_deref().unwrap_or(DEFAULT_REASON))
+    }
+
+    /// Fills in the defaults the settings don't have and forces the locked
+    /// values.
+    pub fn apply(&self, values: &mut Map<String, Value>) {
+        for (key, value) in &self.defaults {
+            settings::set_key(values, key, value.clone(), false);
+        }
+        for (key, value) in &self.locked {
+            settings::set_key(values, key, value.clone(), true);
+        }
+    }
+
+    /// Drops entries that would make the settings unreadable, so a mistake
+    /// in the policy can't cost the user their settings file.
+    fn drop_invalid(&mut self) {
+        let Ok(Value::Object(base)) = serde_json::to_value(AppSettings::default()) else {
+            return;
+        };
+        for (kind, entries) in [("default", &mut self.defaults), ("lock", &mut self.locked)] {
+            entries.retain(|key, value| {
+                let mut values = base.clone();
+                settings::set_key(&mut values, key, value.clone(), true);
+                match serde_json::from_value::<AppSettings>(Value::Object(values)) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        println!("Ignoring policy {} for {}: {}", kind, key, e);
+                        false
+                    }
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(value: Value) -> Policy {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn apply_fills_defaults_and_forces_locked_values() {
+        let policy = policy(json!({
+            "defaults": {"theme": "dark", "download_path": "/tmp"},
+            "locked": {"notification_settings.enabled": false}
+        }));
+        let Value::Object(mut values) = json!({
+            "theme": "light",
+            "notification_settings": {"enabled": true}
+        }) else {
+            unreachable!()
+        };
+
+        policy.apply(&mut values);
+
+        assert_eq!(
+            Value::Object(values),
+            json!({
+                "theme": "light",
+                "download_path": "/tmp",
+                "notification_settings": {"enabled": false}
+            })
+        );
+    }
+
+    #[test]
+    fn locking_a_key_locks_the_keys_below_it() {
+        let policy = policy(json!({
+            "reason": "Set by IT",
+            "locked": {"notification_settings": {}, "tabs[0].name": "Work"}
+        }));
+
+        assert_eq!(
+            policy.lock_reason("notification_settings.enabled"),
+            Some("Set by IT")
+        );
+        assert_eq!(policy.lock_reason("tabs.0.name"), Some("Set by IT"));
+        assert_eq!(policy.lock_reason("tabs.1.name"), None);
+        assert_eq!(policy.lock_reason("tabs"), None);
+        assert_eq!(policy.lock_reason("notification"), None);
+    }
+
+    #[test]
+    fn lock_reason_falls_back_to_the_default() {
+        let policy = policy(json!({"locked": {"theme": "dark"}}));
+        assert_eq!(policy.lock_reason("theme"), Some(DEFAULT_REASON));
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::paths;
+use crate::settings::SETTINGS_FILE;
+
+/// The profile kept directly in the config directory.
+pub const DEFAULT_PROFILE: &str = "default";
+/// Named profiles live in `<config>/profiles/<name>/settings.json`.
+const PROFILES_DIR: &str = "profiles";
+/// Remembers the profile used last, for starts without `--profile`.
+const ACTIVE_PROFILE_FILE: &str = "active_profile";
+
+static PROFILE_ARG: OnceLock<Option<String>> = OnceLock::new();
+
+pub fn init(profile_arg: Option<String>) {
+    let profile_arg = profile_arg.filter(|name| {
+        let valid = is_valid_name(name);
+        if !valid {
+            println!("Ignoring invalid profile name {:?}", name);
+        }
+        valid
+    });
+    if PROFILE_ARG.set(profile_arg).is_err() {
+        println!("Settings profile was already initialized");
+    }
+}
+
+/// `--profile`, then the profile used last, then the default one.
+pub fn startup_profile() -> String {
+    if let Some(Some(name)) = PROFILE_ARG.get() {
+        return name.clone();
+    }
+    paths::config_dir()
+        .and_then(|cfg| fs::read_to_string(cfg.join(ACTIVE_PROFILE_FILE)).ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| is_valid_name(name))
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+pub fn set_active(name: &str) {
+    let Some(cfg) = paths::config_dir() else {
+        return;
+    };
+    if let Err(e) = fs::write(cfg.join(ACTIVE_PROFILE_FILE), name) {
+        println!("Failed to remember profile {}: {}", name, e);
+    }
+}
+
+/// Letters, digits, `-` and `_`, so a name is always a plain directory name.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Directory holding the profile's `settings.json`, created when missing.
+pub fn dir(name: &str) -> Option<PathBuf> {
+    let cfg = paths::config_dir()?;
+    if name == DEFAULT_PROFILE {
+        return Some(cfg);
+    }
+    let dir = cfg.join(PROFILES_DIR).join(name);
+    if let Err(e) = fs::create_dir_all(&dir) {
+        println!("Failed to create {}: {}", dir.display(), e);
+    }
+    Some(dir)
+}
+
+pub fn exists(name: &str) -> bool {
+    name == DEFAULT_PROFILE
+        || paths::config_dir().is_some_and(|cfg| {
+            cfg.join(PROFILES_DIR)
+                .join(name)
+                .join(SETTINGS_FILE)
+                .exists()
+        })
+}
+
+/// The default profile followed by the named ones, sorted.
+pub fn list() -> Vec<String> {
+    let mut names: Vec<String> = paths::config_dir()
+        .and_then(|cfg| fs::read_dir(cfg.join(PROFILES_DIR)).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().join(SETTINGS_FILE).exists())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| is_valid_name(name) && name != DEFAULT_PROFILE)
+        .collect();
+    names.sort();
+    names.insert(0, DEFAULT_PROFILE.to_string());
+    names
+}
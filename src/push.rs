@@ -0,0 +1,529 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::notification::{NotificationEvent, NotificationEventKind};
+use crate::runtime::runtime;
+
+pub const QUEUE_FILE: &str = "push_queue.json";
+const MAX_QUEUED_PER_TARGET: usize = 200;
+const MAX_QUEUE_AGE_SECS: i64 = 24 * 60 * 60;
+const MIN_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "service", rename_all = "lowercase")]
+pub enum PushService {
+    Ntfy {
+        #[serde(default = "default_ntfy_server")]
+        server: String,
+        topic: String,
+        #[serde(default)]
+        token: String,
+    },
+    Gotify {
+        server: String,
+        token: String,
+    },
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+/// Selects which events a target forwards. Empty fields match anything,
+/// `chat` is a case-insensitive substring of the chat or sender name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PushFilter {
+    #[serde(default)]
+    pub account: String,
+    #[serde(default)]
+    pub chat: String,
+    #[serde(default)]
+    pub events: Vec<NotificationEventKind>,
+}
+
+impl PushFilter {
+    fn matches(&self, event: &NotificationEvent) -> bool {
+        let chat = self.chat.to_lowercase();
+        (self.account.is_empty() || self.account == event.account)
+            && (chat.is_empty()
+                || event.chat.to_lowercase().contains(&chat)
+                || event.sender.to_lowercase().contains(&chat))
+            && (self.events.is_empty() || self.events.contains(&event.event))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushTarget {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub service: PushService,
+    /// Forward only events matching one of these, or everything when empty.
+    #[serde(default)]
+    pub filters: Vec<PushFilter>,
+    /// Forward only while the app window is not active.
+    #[serde(default)]
+    pub only_when_away: bool,
+    #[serde(default)]
+    pub redact_text: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl PushTarget {
+    fn accepts(&self, event: &NotificationEvent, away: bool) -> bool {
+        self.enabled
+            && (away || !self.only_when_away)
+            && (self.filters.is_empty() || self.filters.iter().any(|f| f.matches(event)))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueuedPush {
+    event: NotificationEvent,
+    queued_at: i64,
+    #[serde(default)]
+    attempts: u32,
+}
+
+#[derive(Default)]
+struct TargetQueue {
+    items: VecDeque<QueuedPush>,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+enum Command {
+    Configure(Vec<PushTarget>),
+    Forward {
+        event: NotificationEvent,
+        away: bool,
+    },
+}
+
+/// Forwards notification events to HTTP push services from a background
+/// task. Undeliverable events stay queued (and on disk) and are retried with
+/// exponential backoff.
+pub struct PushForwarder {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl PushForwarder {
+    pub fn start(queue_path: Option<PathBuf>) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        runtime().spawn(Worker::new(queue_path).run(receiver));
+        Self { commands }
+    }
+
+    pub fn configure(&self, targets: Vec<PushTarget>) {
+        let _ = self.commands.send(Command::Configure(targets));
+    }
+
+    pub fn forward(&self, event: NotificationEvent, away: bool) {
+        let _ = self.commands.send(Command::Forward { event, away });
+    }
+}
+
+struct Worker {
+    client: reqwest::Client,
+    targets: Vec<PushTarget>,
+    queues: HashMap<String, TargetQueue>,
+    queue_path: Option<PathBuf>,
+}
+
+impl Worker {
+    fn new(queue_path: Option<PathBuf>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        let mut worker = Self {
+            client,
+            targets: Vec::new(),
+            queues: HashMap::new(),
+            queue_path,
+        };
+        worker.load_queue();
+        worker
+    }
+
+    async fn run(mut self, mut receiver: mpsc::UnboundedReceiver<Command>) {
+        loop {
+            let next_retry = self
+                .queues
+                .values()
+                .filter(|q| !q.items.is_empty())
+                .filter_map(|q| q.retry_at)
+                .min();
+
+            tokio::select! {
+                command = receiver.recv() => match command {
+                    Some(Command::Configure(targets)) => self.configure(targets),
+                    Some(Command::Forward { event, away }) => self.enqueue(event, away),
+                    None => break,
+                },
+                _ = sleep_until(next_retry) => {}
+            }
+
+            self.flush().await;
+        }
+    }
+
+    /// Drops the queued events of targets that were removed, they could
+    /// never be delivered.
+    fn configure(&mut self, targets: Vec<PushTarget>) {
+        let before = self.queues.len();
+        self.queues
+            .retain(|name, _| targets.iter().any(|target| &target.name == name));
+        self.targets = targets;
+        if self.queues.len() != before {
+            self.save_queue();
+        }
+    }
+
+    fn enqueue(&mut self, event: NotificationEvent, away: bool) {
+        let mut changed = false;
+        for target in self.targets.iter().filter(|t| t.accepts(&event, away)) {
+            let mut event = event.clone();
+            if target.redact_text && !event.text.is_empty() {
+                event.text = "[redacted]".to_string();
+            }
+            let queue = self.queues.entry(target.name.clone()).or_default();
+            queue.items.push_back(QueuedPush {
+                event,
+                queued_at: Local::now().timestamp(),
+                attempts: 0,
+            });
+            while queue.items.len() > MAX_QUEUED_PER_TARGET {
+                queue.items.pop_front();
+            }
+            changed = true;
+        }
+        if changed {
+            self.save_queue();
+        }
+    }
+
+    /// Sends queued events for every target that is not backing off. The
+    /// targets are sent to concurrently, so one that times out doesn't hold
+    /// up the others.
+    async fn flush(&mut self) {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for target in &self.targets {
+            let Some(queue) = self.queues.remove(&target.name) else {
+                continue;
+            };
+            if queue.retry_at.is_some_and(|at| at > now) {
+                self.queues.insert(target.name.clone(), queue);
+                continue;
+            }
+            due.push(drain(&self.client, target, queue));
+        }
+        if due.is_empty() {
+            return;
+        }
+
+        let mut changed = false;
+        for (name, queue, drained) in futures::future::join_all(due).await {
+            changed |= drained;
+            self.queues.insert(name, queue);
+        }
+        if changed {
+            self.save_queue();
+        }
+    }
+
+    fn load_queue(&mut self) {
+        let Some(path) = &self.queue_path else {
+            return;
+        };
+        let Ok(data) = fs::read_to_string(path) else {
+            return;
+        };
+        match serde_json::from_str::<HashMap<String, VecDeque<QueuedPush>>>(&data) {
+            Ok(saved) => {
+                self.queues = saved
+                    .into_iter()
+                    .map(|(name, items)| {
+                        (
+                            name,
+                            TargetQueue {
+                                items,
+                                ..Default::default()
+                            },
+                        )
+                    })
+                    .collect();
+            }
+            Err(e) => println!("Ignoring unreadable push queue: {}", e),
+        }
+    }
+
+    fn save_queue(&self) {
+        let Some(path) = &self.queue_path else {
+            return;
+        };
+        let saved: HashMap<&String, &VecDeque<QueuedPush>> = self
+            .queues
+            .iter()
+            .filter(|(_, q)| !q.items.is_empty())
+            .map(|(name, q)| (name, &q.items))
+            .collect();
+        let result = if saved.is_empty() {
+            fs::remove_file(path).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            })
+        } else {
+            serde_json::to_string(&saved)
+                .map_err(std::io::Error::other)
+                .and_then(|json| fs::write(path, json))
+        };
+        if let Err(e) = result {
+            println!("Failed to save push queue: {}", e);
+        }
+    }
+}
+
+/// Sends `target`'s queued events in order until one fails. Returns the
+/// queue and whether it changed.
+async fn drain(
+    client: &reqwest::Client,
+    target: &PushTarget,
+    mut queue: TargetQueue,
+) -> (String, TargetQueue, bool) {
+    let oldest = Local::now().timestamp() - MAX_QUEUE_AGE_SECS;
+    let before = queue.items.len();
+    queue.items.retain(|item| item.queued_at >= oldest);
+    let mut changed = queue.items.len() != before;
+
+    while let Some(item) = queue.items.front_mut() {
+        match send(client, &target.service, &item.event).await {
+            Ok(()) => {
+                queue.items.pop_front();
+                queue.backoff = Duration::ZERO;
+                queue.retry_at = None;
+                changed = true;
+            }
+            Err(e) => {
+                item.attempts += 1;
+                queue.backoff = (queue.backoff * 2).clamp(MIN_BACKOFF, MAX_BACKOFF);
+                queue.retry_at = Some(Instant::now() + queue.backoff);
+                println!(
+                    "Push to '{}' failed (attempt {}), retrying in {}s: {}",
+                    target.name,
+                    item.attempts,
+                    queue.backoff.as_secs(),
+                    e
+                );
+                changed = true;
+                break;
+            }
+        }
+    }
+    (target.name.clone(), queue, changed)
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+fn title_for(event: &NotificationEvent) -> String {
+    match event.call_kind {
+        Some(kind) => format!("Incoming {} call ({})", kind.label(), event.account),
+        None if event.sender != event.chat => {
+            format!("{} in {} ({})", event.sender, event.chat, event.account)
+        }
+        None => format!("{} ({})", event.sender, event.account),
+    }
+}
+
+fn body_for(event: &NotificationEvent) -> String {
+    match event.call_kind {
+        Some(kind) => format!("{} is calling ({} call)", event.sender, kind.label()),
+        None => event.text.clone(),
+    }
+}
+
+async fn send(
+    client: &reqwest::Client,
+    service: &PushService,
+    event: &NotificationEvent,
+) -> Result<(), reqwest::Error> {
+    let is_call = event.event == NotificationEventKind::Call;
+    let request = match service {
+        PushService::Ntfy {
+            server,
+            topic,
+            token,
+        } => {
+            let mut request = client
+                .post(format!("{}/{}", server.trim_end_matches('/'), topic))
+                .header("Title", title_for(event))
+                .header("Priority", if is_call { "urgent" } else { "default" })
+                .header(
+                    "Tags",
+                    if is_call {
+                        "telephone_receiver"
+                    } else {
+                        "speech_balloon"
+                    },
+                )
+                .body(body_for(event));
+            if !token.is_empty() {
+                request = request.bearer_auth(token);
+            }
+            request
+        }
+        PushService::Gotify { server, token } => client
+            .post(format!("{}/message", server.trim_end_matches('/')))
+            .header("X-Gotify-Key", token)
+            .json(&serde_json::json!({
+                "title": title_for(event),
+                "message": body_for(event),
+                "priority": if is_call { 8 } else { 5 },
+            })),
+        PushService::Webhook { url, headers } => {
+            let mut request = client.post(url).json(event);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request
+        }
+    };
+
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn webhook(name: &str, listener: &TcpListener) -> PushTarget {
+        PushTarget {
+            name: name.to_string(),
+            enabled: true,
+            service: PushService::Webhook {
+                url: format!("http://{}/", listener.local_addr().unwrap()),
+                headers: HashMap::new(),
+            },
+            filters: Vec::new(),
+            only_when_away: false,
+            redact_text: false,
+        }
+    }
+
+    fn event() -> NotificationEvent {
+        NotificationEvent::message(0, "Work", "Alice", "Hello", None)
+    }
+
+    /// Answers each request with 200 and reports its raw text.
+    fn serve(listener: TcpListener) -> mpsc::UnboundedReceiver<String> {
+        let (requests, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0; 4096];
+                while let Ok(n @ 1..) = stream.read(&mut buf).await {
+                    request.extend_from_slice(&buf[..n]);
+                    if complete(&request) {
+                        break;
+                    }
+                }
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                    .await;
+                let _ = requests.send(String::from_utf8_lossy(&request).into_owned());
+            }
+        });
+        received
+    }
+
+    /// Whether the headers and the `Content-Length` body have arrived.
+    fn complete(request: &[u8]) -> bool {
+        let text = String::from_utf8_lossy(request);
+        let Some((head, body)) = text.split_once("\r\n\r\n") else {
+            return false;
+        };
+        let length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse().ok())
+            .unwrap_or(0);
+        body.len() >= length
+    }
+
+    #[tokio::test]
+    async fn delivers_webhook_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = webhook("hook", &listener);
+        let mut received = serve(listener);
+
+        let mut worker = Worker::new(None);
+        worker.configure(vec![target]);
+        worker.enqueue(event(), false);
+        worker.flush().await;
+
+        let request = received.recv().await.unwrap();
+        assert!(request.starts_with("POST / "));
+        assert!(request.contains("\"chat\":\"Alice\""));
+        assert!(worker.queues["hook"].items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stalled_target_does_not_hold_up_others() {
+        // Accepts connections but never answers them.
+        let stalled = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let targets = vec![webhook("stalled", &stalled), webhook("hook", &listener)];
+        let mut received = serve(listener);
+
+        let mut worker = Worker::new(None);
+        worker.configure(targets);
+        worker.enqueue(event(), false);
+        tokio::spawn(async move {
+            worker.flush().await;
+            drop(stalled);
+        });
+
+        let request = tokio::time::timeout(Duration::from_secs(5), received.recv()).await;
+        assert!(request.is_ok_and(|request| request.is_some()));
+    }
+
+    #[tokio::test]
+    async fn removing_a_target_drops_its_queue() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let mut worker = Worker::new(None);
+        worker.configure(vec![webhook("old", &listener), webhook("kept", &listener)]);
+        worker.enqueue(event(), false);
+        worker.configure(vec![webhook("kept", &listener)]);
+
+        assert!(!worker.queues.contains_key("old"));
+        assert_eq!(worker.queues["kept"].items.len(), 1);
+    }
+}
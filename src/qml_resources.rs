@@ -1,4 +1,6 @@
 pub const SIDEBAR_QML: &str = include_str!("../qml/components/Sidebar.qml");
 pub const WEBTAB_QML: &str = include_str!("../qml/components/WebTab.qml");
 pub const SETTINGS_DIALOG_QML: &str = include_str!("../qml/components/SettingsDialog.qml");
+pub const NOTIFICATION_CENTER_QML: &str = include_str!("../qml/components/NotificationCenter.qml");
 pub const APP_STYLE_QML: &str = include_str!("../qml/styles/AppStyle.qml");
+pub const PAGE_BRIDGE_JS: &str = include_str!("../resources/scripts/page_bridge.js");
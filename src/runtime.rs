@@ -0,0 +1,17 @@
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Shared tokio runtime for background I/O, so network and disk work stays
+/// off the Qt GUI thread.
+pub fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("whatsapp-qt-io")
+            .enable_all()
+            .build()
+            .expect("Failed to start background runtime")
+    })
+}
@@ -0,0 +1,380 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+use crate::paths;
+
+/// Prefix of encrypted setting values: `enc:v1:<base64(nonce || ciphertext)>`.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+/// Encrypted with the key to tell a wrong passphrase from a corrupted value.
+const CHECK_PLAINTEXT: &str = "whatsapp-qt";
+const PASSPHRASE_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    /// Random key stored in the freedesktop Secret Service.
+    Keyring,
+    /// Key derived from a passphrase typed on the terminal.
+    Passphrase,
+}
+
+/// Stored next to the encrypted fields so they can be decrypted again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyInfo {
+    pub source: KeySource,
+    /// Argon2 salt for passphrase keys, base64.
+    #[serde(default)]
+    pub salt: String,
+    /// `CHECK_PLAINTEXT` encrypted with the key.
+    pub check: String,
+}
+
+#[derive(Debug)]
+pub enum SecretError {
+    NoKey,
+    Invalid(String),
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecretError::NoKey => write!(
+                f,
+                "No keyring or passphrase available to decrypt the encrypted settings"
+            ),
+            SecretError::Invalid(e) => write!(f, "Failed to decrypt a setting: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+pub struct FieldCipher {
+    key: Secret<[u8; 32]>,
+    info: KeyInfo,
+}
+
+impl FieldCipher {
+    fn new(key: [u8; 32], source: KeySource, salt: String) -> Self {
+        let mut cipher = Self {
+            key: Secret::new(key),
+            info: KeyInfo {
+                source,
+                salt,
+                check: String::new(),
+            },
+        };
+        cipher.info.check = cipher.encrypt(CHECK_PLAINTEXT);
+        cipher
+    }
+
+    pub fn info(&self) -> &KeyInfo {
+        &self.info
+    }
+
+    fn verifies(&self, info: &KeyInfo) -> bool {
+        self.decrypt(&info.check)
+            .is_ok_and(|check| check == CHECK_PLAINTEXT)
+    }
+
+    pub fn encrypt(&self, plain: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rng().fill(&mut nonce[..]);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(self.key.expose_secret()));
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            cipher
+                .encrypt(Nonce::from_slice(&nonce), plain.as_bytes())
+                .expect("encrypting in memory can not fail"),
+        );
+        format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode(sealed))
+    }
+
+    pub fn decrypt(&self, value: &str) -> Result<String, SecretError> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(value.to_string());
+        };
+        let sealed = BASE64
+            .decode(encoded)
+            .map_err(|e| SecretError::Invalid(e.to_string()))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(SecretError::Invalid("value too short".to_string()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(self.key.expose_secret()));
+        let plain = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| SecretError::Invalid("wrong key or corrupted value".to_string()))?;
+        String::from_utf8(plain).map_err(|e| SecretError::Invalid(e.to_string()))
+    }
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// The outcome is kept for the process, failures too: without a keyring
+/// every save would otherwise wait for the D-Bus timeout again.
+static CIPHER: OnceLock<Option<FieldCipher>> = OnceLock::new();
+
+/// The settings key for this session, set up on first use. `info` describes
+/// the key the stored settings were encrypted with, if any. Can wait for a
+/// keyring prompt or a passphrase, so never call it on the GUI thread.
+pub fn cipher(info: Option<&KeyInfo>) -> Option<&'static FieldCipher> {
+    CIPHER.get_or_init(|| open_cipher(info)).as_ref()
+}
+
+/// The settings key if it is already open, without asking for it.
+pub fn unlocked() -> Option<&'static FieldCipher> {
+    CIPHER.get().and_then(Option::as_ref)
+}
+
+fn open_cipher(info: Option<&KeyInfo>) -> Option<FieldCipher> {
+    match info.map(|info| info.source) {
+        Some(KeySource::Passphrase) => passphrase_cipher(info),
+        // Never replace a keyring key with a passphrase, the values
+        // encrypted with it would become unreadable for good.
+        Some(KeySource::Keyring) => keyring_cipher(info),
+        // A separate home may travel between machines, the keyring does not.
+        None if paths::home().is_some() => passphrase_cipher(None).or_else(|| keyring_cipher(None)),
+        None => keyring_cipher(None).or_else(|| passphrase_cipher(None)),
+    }
+}
+
+fn keyring_cipher(info: Option<&KeyInfo>) -> Option<FieldCipher> {
+    match keyring::settings_key() {
+        Ok(key) => {
+            let cipher = FieldCipher::new(key, KeySource::Keyring, String::new());
+            if info.is_none_or(|info| cipher.verifies(info)) {
+                return Some(cipher);
+            }
+            println!("The keyring holds a different settings key");
+        }
+        Err(e) => println!("Secret Service unavailable: {}", e),
+    }
+    None
+}
+
+/// Asks for the settings passphrase when a terminal is attached.
+fn passphrase_cipher(info: Option<&KeyInfo>) -> Option<FieldCipher> {
+    if !std::io::stdin().is_terminal() {
+        println!(
+            "No terminal to ask for a settings passphrase, sensitive settings stay unencrypted"
+        );
+        return None;
+    }
+
+    let salt = match info.filter(|info| info.source == KeySource::Passphrase) {
+        Some(info) => BASE64.decode(&info.salt).ok()?,
+        None => {
+            let mut salt = vec![0u8; 16];
+            rand::rng().fill(&mut salt[..]);
+            salt
+        }
+    };
+
+    for _ in 0..PASSPHRASE_ATTEMPTS {
+        let passphrase = match rpassword::prompt_password("WhatsApp-QT settings passphrase: ") {
+            Ok(passphrase) => SecretString::new(passphrase),
+            Err(e) => {
+                println!("Failed to read passphrase: {}", e);
+                return None;
+            }
+        };
+        let key = match derive_key(&passphrase, &salt) {
+            Ok(key) => key,
+            Err(e) => {
+                println!("Failed to derive settings key: {}", e);
+                return None;
+            }
+        };
+        let cipher = FieldCipher::new(
+            *key.expose_secret(),
+            KeySource::Passphrase,
+            BASE64.encode(&salt),
+        );
+        match info {
+            Some(info) if info.source == KeySource::Passphrase && !cipher.verifies(info) => {
+                println!("Wrong passphrase");
+            }
+            _ => return Some(cipher),
+        }
+    }
+    None
+}
+
+/// Argon2 key for `passphrase`, shared by settings and bundle encryption.
+pub fn derive_key(passphrase: &SecretString, salt: &[u8]) -> Result<Secret<[u8; 32]>, String> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.expose_secret().as_bytes(), salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(Secret::new(key))
+}
+
+/// Reads a passphrase from the terminal, twice when `confirm` is set.
+pub fn prompt_passphrase(prompt: &str, confirm: bool) -> Result<SecretString, String> {
+    if !std::io::stdin().is_terminal() {
+        return Err("A terminal is needed to ask for the passphrase".to_string());
+    }
+    let read = |prompt: &str| {
+        rpassword::prompt_password(prompt)
+            .map(SecretString::new)
+            .map_err(|e| format!("Failed to read passphrase: {}", e))
+    };
+    let passphrase = read(prompt)?;
+    if passphrase.expose_secret().is_empty() {
+        return Err("The passphrase can't be empty".to_string());
+    }
+    if confirm && read("Repeat passphrase: ")?.expose_secret() != passphrase.expose_secret() {
+        return Err("The passphrases don't match".to_string());
+    }
+    Ok(passphrase)
+}
+
+#[cfg(target_os = "linux")]
+mod keyring {
+    use dbus::Path;
+    use dbus::arg::{PropMap, RefArg, Variant};
+    use dbus::blocking::Connection;
+    use dbus::message::MatchRule;
+    use rand::Rng;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    const SERVICE: &str = "org.freedesktop.secrets";
+    const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+    const SERVICE_IFACE: &str = "org.freedesktop.Secret.Service";
+    const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    /// How long the user has to answer an unlock prompt.
+    const PROMPT_TIMEOUT: Duration = Duration::from_secs(120);
+
+    type Error = Box<dyn std::error::Error>;
+    /// `(session, parameters, value, content_type)`
+    type SecretValue = (Path<'static>, Vec<u8>, Vec<u8>, String);
+
+    /// Looks up the settings key, creating a random one on first use.
+    pub fn settings_key() -> Result<[u8; 32], Error> {
+        let conn = Connection::new_session()?;
+        let service = conn.with_proxy(SERVICE, SERVICE_PATH, TIMEOUT);
+        // Plain sessions are fine, the secret only travels over the
+        // user's session bus.
+        let (_, session): (Variant<Box<dyn RefArg>>, Path<'static>) = service.method_call(
+            SERVICE_IFACE,
+            "OpenSession",
+            ("plain", Variant(String::new())),
+        )?;
+
+        let attributes = HashMap::from([
+            ("application".to_string(), "whatsapp-qt".to_string()),
+            ("purpose".to_string(), "settings-key".to_string()),
+        ]);
+        let (unlocked, locked): (Vec<Path<'static>>, Vec<Path<'static>>) =
+            service.method_call(SERVICE_IFACE, "SearchItems", (attributes.clone(),))?;
+        let item = match unlocked.into_iter().next() {
+            Some(item) => Some(item),
+            None if !locked.is_empty() => {
+                unlock(&conn, locked.clone())?;
+                locked.into_iter().next()
+            }
+            None => None,
+        };
+
+        if let Some(item) = item {
+            let (secret,): (SecretValue,) = conn.with_proxy(SERVICE, item, TIMEOUT).method_call(
+                "org.freedesktop.Secret.Item",
+                "GetSecret",
+                (session,),
+            )?;
+            return secret
+                .2
+                .try_into()
+                .map_err(|_| "stored settings key has the wrong length".into());
+        }
+
+        let mut key = [0u8; 32];
+        rand::rng().fill(&mut key[..]);
+        unlock(&conn, vec![Path::from(DEFAULT_COLLECTION)])?;
+
+        let mut properties = PropMap::new();
+        properties.insert(
+            "org.freedesktop.Secret.Item.Label".to_string(),
+            Variant(Box::new("WhatsApp-QT settings key".to_string()) as Box<dyn RefArg>),
+        );
+        properties.insert(
+            "org.freedesktop.Secret.Item.Attributes".to_string(),
+            Variant(Box::new(attributes) as Box<dyn RefArg>),
+        );
+        let secret: SecretValue = (
+            session,
+            Vec::new(),
+            key.to_vec(),
+            "application/octet-stream".to_string(),
+        );
+        let (_item, prompt): (Path<'static>, Path<'static>) = conn
+            .with_proxy(SERVICE, DEFAULT_COLLECTION, TIMEOUT)
+            .method_call(
+                "org.freedesktop.Secret.Collection",
+                "CreateItem",
+                (properties, secret, true),
+            )?;
+        run_prompt(&conn, prompt)?;
+        Ok(key)
+    }
+
+    fn unlock(conn: &Connection, objects: Vec<Path<'static>>) -> Result<(), Error> {
+        let (_, prompt): (Vec<Path<'static>>, Path<'static>) = conn
+            .with_proxy(SERVICE, SERVICE_PATH, TIMEOUT)
+            .method_call(SERVICE_IFACE, "Unlock", (objects,))?;
+        run_prompt(conn, prompt)
+    }
+
+    /// Shows a keyring prompt and waits for the user to complete it.
+    fn run_prompt(conn: &Connection, prompt: Path<'static>) -> Result<(), Error> {
+        if &*prompt == "/" {
+            return Ok(());
+        }
+
+        let dismissed = Arc::new(Mutex::new(None));
+        let result = Arc::clone(&dismissed);
+        let rule = MatchRule::new_signal("org.freedesktop.Secret.Prompt", "Completed")
+            .with_path(prompt.clone());
+        conn.add_match(
+            rule,
+            move |(was_dismissed, _): (bool, Variant<Box<dyn RefArg>>), _, _| {
+                *result.lock().unwrap() = Some(was_dismissed);
+                false
+            },
+        )?;
+        conn.with_proxy(SERVICE, prompt, TIMEOUT)
+            .method_call::<(), _, _, _>("org.freedesktop.Secret.Prompt", "Prompt", ("",))?;
+
+        let deadline = Instant::now() + PROMPT_TIMEOUT;
+        while dismissed.lock().unwrap().is_none() && Instant::now() < deadline {
+            conn.process(Duration::from_millis(200))?;
+        }
+        match *dismissed.lock().unwrap() {
+            Some(false) => Ok(()),
+            Some(true) => Err("keyring prompt was dismissed".into()),
+            None => Err("keyring prompt timed out".into()),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod keyring {
+    pub fn settings_key() -> Result<[u8; 32], Box<dyn std::error::Error>> {
+        Err("the Secret Service is only available on Linux".into())
+    }
+}
@@ -1 +1,492 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use crate::backup::BackupSettings;
+use crate::css::ThemeStrategy;
+use crate::notification::NotificationSettings;
+use crate::paths;
+use crate::policy;
+use crate::push::PushService;
+use crate::secrets::{self, FieldCipher, KeyInfo, SecretError};
+
+pub const SETTINGS_FILE: &str = "settings.json";
+pub const SCHEMA_VERSION: u32 = 2;
+/// Services tabs can load, keys of `theme_strategies`.
+pub const SERVICES: &[&str] = &[WHATSAPP_SERVICE];
+pub const WHATSAPP_SERVICE: &str = "whatsapp";
+
+/// `MIGRATIONS[n]` upgrades a version `n` file to version `n + 1`.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[migrate_v0_to_v1, migrate_v1_to_v2];
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AppSettings {
+    #[serde(default)]
+    pub schema_version: u32,
+    #[serde(default = "default_theme")]
+    pub theme: String,
+    /// How each service's pages follow `theme`, keyed by service.
+    #[serde(default = "default_theme_strategies")]
+    pub theme_strategies: BTreeMap<String, ThemeStrategy>,
+    #[serde(default = "default_download_path")]
+    pub download_path: String,
+    #[serde(default)]
+    pub current_tab: i32,
+    #[serde(default)]
+    pub notification_settings: NotificationSettings,
+    #[serde(default)]
+    pub tabs: Vec<TabInfo>,
+    #[serde(default)]
+    pub backup: BackupSettings,
+    /// Key used for the encrypted fields, absent while they are plain text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<KeyInfo>,
+}
+
+fn default_theme() -> String {
+    "system".to_string()
+}
+
+pub fn default_theme_strategies() -> BTreeMap<String, ThemeStrategy> {
+    SERVICES
+        .iter()
+        .map(|service| (service.to_string(), ThemeStrategy::default()))
+        .collect()
+}
+
+pub fn default_download_path() -> String {
+    paths::download_dir().to_string_lossy().to_string()
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            theme: default_theme(),
+            theme_strategies: default_theme_strategies(),
+            download_path: default_download_path(),
+            current_tab: 0,
+            notification_settings: NotificationSettings::default(),
+            tabs: Vec::new(),
+            backup: BackupSettings::default(),
+            encryption: None,
+        }
+    }
+}
+
+impl AppSettings {
+    /// Account names and push credentials, the values kept encrypted on disk.
+    fn sensitive_fields(&mut self) -> Vec<&mut String> {
+        let mut fields: Vec<&mut String> = self.tabs.iter_mut().map(|t| &mut t.name).collect();
+        let notifications = &mut self.notification_settings;
+        fields.extend(notifications.rules.iter_mut().map(|r| &mut r.account));
+        for target in &mut notifications.push_targets {
+            match &mut target.service {
+                PushService::Ntfy { token, .. } | PushService::Gotify { token, .. } => {
+                    fields.push(token)
+                }
+                PushService::Webhook { headers, .. } => fields.extend(headers.values_mut()),
+            }
+            fields.extend(target.filters.iter_mut().map(|f| &mut f.account));
+        }
+        fields.retain(|field| !field.is_empty());
+        fields
+    }
+
+    /// Encrypts the sensitive fields with the settings key. Without a keyring
+    /// or passphrase they are written as they are. `SettingsWriter` does this
+    /// on its own task, opening the key can wait for a prompt.
+    pub fn encrypt_fields(&mut self) {
+        let Some(cipher) = secrets::cipher(self.encryption.as_ref()) else {
+            return;
+        };
+        for field in self.sensitive_fields() {
+            // Values that could not be decrypted on load are kept as they were.
+            if !secrets::is_encrypted(field) {
+                *field = cipher.encrypt(field);
+            }
+        }
+        self.encryption = Some(cipher.info().clone());
+    }
+
+    /// Decrypts the sensitive fields in place. Values that fail to decrypt
+    /// stay encrypted so the next save does not lose them.
+    pub fn decrypt_fields(&mut self) -> Result<(), SecretError> {
+        let info = self.encryption.clone();
+        self.decrypt_with(|| secrets::cipher(info.as_ref()))
+    }
+
+    /// Like `decrypt_fields`, but only with a key that is already open, so
+    /// the GUI thread never waits for a prompt.
+    pub fn decrypt_fields_unlocked(&mut self) -> Result<(), SecretError> {
+        self.decrypt_with(secrets::unlocked)
+    }
+
+    fn decrypt_with(
+        &mut self,
+        cipher: impl FnOnce() -> Option<&'static FieldCipher>,
+    ) -> Result<(), SecretError> {
+        if !self
+            .sensitive_fields()
+            .iter()
+            .any(|field| secrets::is_encrypted(field))
+        {
+            return Ok(());
+        }
+        let Some(cipher) = cipher() else {
+            return Err(SecretError::NoKey);
+        };
+        let mut result = Ok(());
+        for field in self.sensitive_fields() {
+            match cipher.decrypt(field) {
+                Ok(plain) => *field = plain,
+                Err(e) => result = Err(e),
+            }
+        }
+        result
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TabInfo {
+    pub name: String,
+    #[serde(default = "default_tab_icon")]
+    pub icon: String,
+    /// Web profile directory of the account, e.g. `whatsapp_tab_0`. Settings
+    /// profiles that list the same storage share the login.
+    #[serde(default)]
+    pub storage: String,
+}
+
+/// Web profile directory name for account slot `n`.
+pub fn tab_storage(n: usize) -> String {
+    format!("whatsapp_tab_{}", n)
+}
+
+fn default_tab_icon() -> String {
+    "💬".to_string()
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(std::io::Error),
+    /// The file could not be parsed, it was moved to `backup` when possible.
+    Corrupt {
+        message: String,
+        backup: Option<PathBuf>,
+    },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Io(e) => write!(f, "Failed to read settings: {}", e),
+            SettingsError::Corrupt {
+                message,
+                backup: Some(backup),
+            } => write!(
+                f,
+                "Settings file is corrupted ({}), it was moved to {} and defaults are used",
+                message,
+                backup.display()
+            ),
+            SettingsError::Corrupt {
+                message,
+                backup: None,
+            } => write!(f, "Settings file is corrupted ({})", message),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// Reads `settings.json` from `dir`, upgrading older schema versions.
+/// Returns `Ok(None)` when there is no settings file yet.
+pub fn load(dir: &Path) -> Result<Option<AppSettings>, SettingsError> {
+    let path = dir.join(SETTINGS_FILE);
+    let Some(data) = read(&path)? else {
+        return missing();
+    };
+
+    parse(&data).map(Some).map_err(|message| {
+        let backup = backup_corrupt(&path);
+        SettingsError::Corrupt { message, backup }
+    })
+}
+
+/// The `encryption` entry of the settings file in `dir`. Unlike [`load`] a
+/// file that doesn't parse is left where it is, for the load that reports it.
+pub fn read_encryption(dir: &Path) -> Option<KeyInfo> {
+    let data = fs::read_to_string(dir.join(SETTINGS_FILE)).ok()?;
+    let mut object = match serde_json::from_str::<Value>(&data).ok()? {
+        Value::Object(object) => object,
+        _ => return None,
+    };
+    serde_json::from_value(object.remove("encryption")?).ok()
+}
+
+/// Schema of the settings file in `dir` when a newer version wrote it.
+/// Saving over it would drop the fields this version doesn't know.
+pub fn newer_schema(dir: &Path) -> Option<u32> {
+    let data = fs::read_to_string(dir.join(SETTINGS_FILE)).ok()?;
+    let version = serde_json::from_str::<Value>(&data)
+        .ok()?
+        .get("schema_version")?
+        .as_u64()? as u32;
+    (version > SCHEMA_VERSION).then_some(version)
+}
+
+/// Like [`load`], for a file that changed while running. A file that does
+/// not parse is left in place, it is most likely still being edited.
+pub fn reload(dir: &Path) -> Result<Option<AppSettings>, SettingsError> {
+    let Some(data) = read(&dir.join(SETTINGS_FILE))? else {
+        return missing();
+    };
+    parse(&data)
+        .map(Some)
+        .map_err(|message| SettingsError::Corrupt {
+            message,
+            backup: None,
+        })
+}
+
+/// Without a settings file the policy defaults still apply.
+fn missing() -> Result<Option<AppSettings>, SettingsError> {
+    if policy::get().is_empty() {
+        return Ok(None);
+    }
+    parse("{}")
+        .map(Some)
+        .map_err(|message| SettingsError::Corrupt {
+            message,
+            backup: None,
+        })
+}
+
+fn read(path: &Path) -> Result<Option<String>, SettingsError> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(SettingsError::Io(e)),
+    }
+}
+
+/// Parses a settings document, running the migrations and the policy.
+pub fn parse(data: &str) -> Result<AppSettings, String> {
+    let mut object = match serde_json::from_str::<Value>(data).map_err(|e| e.to_string())? {
+        Value::Object(object) => object,
+        _ => return Err("expected a JSON object".to_string()),
+    };
+
+    let version = object
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32;
+    if version > SCHEMA_VERSION {
+        println!(
+            "Settings were written by a newer version (schema {}), they won't be overwritten",
+            version
+        );
+    }
+    for (from, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        println!("Migrating settings from schema {} to {}", from, from + 1);
+        migrate(&mut object);
+    }
+    object.insert(
+        "schema_version".to_string(),
+        version.max(SCHEMA_VERSION).into(),
+    );
+    policy::get().apply(&mut object);
+
+    let mut settings: AppSettings =
+        serde_json::from_value(Value::Object(object)).map_err(|e| e.to_string())?;
+    // Tabs added by hand or by a policy don't name a web profile.
+    for (index, tab) in settings.tabs.iter_mut().enumerate() {
+        if tab.storage.is_empty() {
+            tab.storage = tab_storage(index);
+        }
+    }
+    settings.download_path = paths::resolve_in_home(&settings.download_path);
+    Ok(settings)
+}
+
+/// Splits `a.b[0].c` or `a.b.0.c` into its parts.
+pub fn key_parts(key: &str) -> Vec<&str> {
+    key.split(['.', '[', ']'])
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Value at the dotted `key`, array elements are addressed by index.
+pub fn get_key<'a>(values: &'a Map<String, Value>, key: &str) -> Option<&'a Value> {
+    let parts = key_parts(key);
+    let (first, rest) = parts.split_first()?;
+    rest.iter()
+        .try_fold(values.get(*first)?, |value, part| match value {
+            Value::Object(object) => object.get(*part),
+            Value::Array(items) => items.get(part.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+/// Sets the dotted `key`, creating missing objects on the way. Array
+/// elements must already exist. Without `overwrite` an existing value is
+/// kept. Returns whether the key could be reached.
+pub fn set_key(values: &mut Map<String, Value>, key: &str, value: Value, overwrite: bool) -> bool {
+    let mut root = Value::Object(std::mem::take(values));
+    let reached = set_in(&mut root, &key_parts(key), value, overwrite);
+    if let Value::Object(object) = root {
+        *values = object;
+    }
+    reached
+}
+
+fn set_in(container: &mut Value, parts: &[&str], value: Value, overwrite: bool) -> bool {
+    let Some((part, rest)) = parts.split_first() else {
+        return false;
+    };
+    if let Value::Array(items) = container {
+        let Some(item) = part.parse::<usize>().ok().and_then(|i| items.get_mut(i)) else {
+            return false;
+        };
+        if rest.is_empty() {
+            if overwrite {
+                *item = value;
+            }
+            return true;
+        }
+        return set_in(item, rest, value, overwrite);
+    }
+
+    if !container.is_object() {
+        *container = Value::Object(Map::new());
+    }
+    let object = container
+        .as_object_mut()
+        .expect("replaced by an object above");
+    if rest.is_empty() {
+        if overwrite || !object.contains_key(*part) {
+            object.insert(part.to_string(), value);
+        }
+        return true;
+    }
+    let child = object
+        .entry(*part)
+        .or_insert_with(|| Value::Object(Map::new()));
+    set_in(child, rest, value, overwrite)
+}
+
+/// Moves an unreadable settings file aside so it is neither lost nor
+/// overwritten by the next save.
+fn backup_corrupt(path: &Path) -> Option<PathBuf> {
+    let backup = path.with_extension(format!(
+        "json.corrupt-{}",
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    match fs::rename(path, &backup) {
+        Ok(()) => Some(backup),
+        Err(e) => {
+            println!("Failed to back up corrupted settings: {}", e);
+            None
+        }
+    }
+}
+
+/// Files from before versioning. Every field added since then has a serde
+/// default, so there is nothing to rewrite yet.
+fn migrate_v0_to_v1(_settings: &mut Map<String, Value>) {}
+
+/// Web profiles used to follow the tab position; pin each tab to the
+/// directory it used so far.
+fn migrate_v1_to_v2(settings: &mut Map<String, Value>) {
+    let Some(Value::Array(tabs)) = settings.get_mut("tabs") else {
+        return;
+    };
+    for (index, tab) in tabs.iter_mut().enumerate() {
+        if let Value::Object(tab) = tab {
+            tab.entry("storage")
+                .or_insert_with(|| tab_storage(index).into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(object) => object,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn get_key_follows_objects_and_indexes() {
+        let values = object(json!({"a": {"b": 1}, "tabs": [{"name": "Work"}]}));
+
+        assert_eq!(get_key(&values, "a.b"), Some(&json!(1)));
+        assert_eq!(get_key(&values, "tabs[0].name"), Some(&json!("Work")));
+        assert_eq!(get_key(&values, "tabs.0.name"), Some(&json!("Work")));
+        assert_eq!(get_key(&values, "tabs[1].name"), None);
+        assert_eq!(get_key(&values, "a.b.c"), None);
+    }
+
+    #[test]
+    fn set_key_creates_objects_and_keeps_values_without_overwrite() {
+        let mut values = object(json!({"a": {"b": 1}}));
+
+        assert!(set_key(&mut values, "a.b", json!(2), false));
+        assert!(set_key(&mut values, "x.y.z", json!(3), false));
+        assert_eq!(
+            Value::Object(values.clone()),
+            json!({"a": {"b": 1}, "x": {"y": {"z": 3}}})
+        );
+
+        assert!(set_key(&mut values, "a.b", json!(2), true));
+        assert_eq!(get_key(&values, "a.b"), Some(&json!(2)));
+    }
+
+    #[test]
+    fn set_key_does_not_add_array_elements() {
+        let mut values = object(json!({"tabs": [{"name": "Work"}]}));
+
+        assert!(set_key(&mut values, "tabs[0].name", json!("Home"), true));
+        assert!(!set_key(&mut values, "tabs[1].name", json!("Other"), true));
+        assert_eq!(Value::Object(values), json!({"tabs": [{"name": "Home"}]}));
+    }
+
+    #[test]
+    fn migrations_reach_the_current_schema() {
+        assert_eq!(MIGRATIONS.len(), SCHEMA_VERSION as usize);
+
+        let mut values = object(json!({
+            "tabs": [{"name": "Work"}, {"name": "Home", "storage": "whatsapp_tab_5"}]
+        }));
+        for migrate in MIGRATIONS {
+            migrate(&mut values);
+        }
+        assert_eq!(
+            get_key(&values, "tabs[0].storage"),
+            Some(&json!("whatsapp_tab_0"))
+        );
+        assert_eq!(
+            get_key(&values, "tabs[1].storage"),
+            Some(&json!("whatsapp_tab_5"))
+        );
+    }
+
+    #[test]
+    fn parse_keeps_a_newer_schema_version() {
+        let settings = parse(r#"{"schema_version": 99}"#).unwrap();
+        assert_eq!(settings.schema_version, 99);
+
+        let settings = parse("{}").unwrap();
+        assert_eq!(settings.schema_version, SCHEMA_VERSION);
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::settings::SETTINGS_FILE;
+
+/// Content of each `settings.json` as last written or applied by the app.
+/// Changes matching it are the app's own and are not reloaded.
+pub type KnownContent = Arc<Mutex<HashMap<PathBuf, String>>>;
+
+/// Watches the config directory with inotify and calls `on_change` from a
+/// background thread when `settings.json` was changed by someone else.
+/// Atomic replacements (rename over the file) are picked up as well.
+#[cfg(target_os = "linux")]
+pub fn watch<F>(dir: PathBuf, known: KnownContent, on_change: F)
+where
+    F: Fn(()) + Send + 'static,
+{
+    use inotify::{Inotify, WatchMask};
+
+    let path = dir.join(SETTINGS_FILE);
+    if let Ok(content) = fs::read_to_string(&path) {
+        known.lock().unwrap().entry(path.clone()).or_insert(content);
+    }
+
+    std::thread::spawn(move || {
+        let mut inotify = match Inotify::init() {
+            Ok(inotify) => inotify,
+            Err(e) => {
+                println!("Cannot watch settings: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = inotify
+            .watches()
+            .add(&dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+        {
+            println!("Cannot watch {}: {}", dir.display(), e);
+            return;
+        }
+
+        let mut buffer = [0u8; 4096];
+        loop {
+            let touched = match inotify.read_events_blocking(&mut buffer) {
+                Ok(mut events) => events
+                    .any(|event| event.name.and_then(|name| name.to_str()) == Some(SETTINGS_FILE)),
+                Err(e) => {
+                    println!("Settings watch stopped: {}", e);
+                    break;
+                }
+            };
+            if !touched {
+                continue;
+            }
+
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            {
+                let mut known = known.lock().unwrap();
+                if known.get(&path) == Some(&content) {
+                    continue;
+                }
+                known.insert(path.clone(), content);
+            }
+            println!("settings.json changed on disk, reloading");
+            on_change(());
+        }
+    });
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch<F>(_dir: PathBuf, _known: KnownContent, _on_change: F)
+where
+    F: Fn(()) + Send + 'static,
+{
+}
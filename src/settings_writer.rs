@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::paths;
+use crate::runtime::runtime;
+use crate::settings::{self, AppSettings, SETTINGS_FILE};
+use crate::settings_watcher::KnownContent;
+
+/// Changes arriving within this window are written once.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// A steady stream of changes is still written at least this often.
+const MAX_DELAY: Duration = DEBOUNCE.saturating_mul(10);
+/// Number of `settings.json.bak.<n>` copies kept, newest is `.bak.1`.
+pub const BACKUP_COUNT: usize = 5;
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Called from the writer task with the outcome of a write.
+pub type SaveReporter = Box<dyn FnOnce(Result<(), String>) + Send>;
+
+enum Command {
+    Save {
+        dir: PathBuf,
+        settings: Box<AppSettings>,
+        report: SaveReporter,
+    },
+    Flush(std_mpsc::Sender<()>),
+}
+
+struct Pending {
+    dir: PathBuf,
+    settings: Box<AppSettings>,
+    report: SaveReporter,
+}
+
+/// The last write to each settings file, before and after encryption.
+/// Encrypting draws a new nonce, so unchanged settings reuse the written
+/// JSON and the write is skipped instead of rotating the backups.
+type Written = HashMap<PathBuf, (String, String)>;
+
+/// Writes `settings.json` from a background task. Bursts of saves are
+/// coalesced and every write goes through a temp file, fsync and rename, so
+/// a crash leaves either the old or the new file behind.
+pub struct SettingsWriter {
+    commands: mpsc::UnboundedSender<Command>,
+    known: KnownContent,
+}
+
+impl SettingsWriter {
+    pub fn start() -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let known: KnownContent = Arc::new(Mutex::new(HashMap::new()));
+        runtime().spawn(run(Arc::clone(&known), receiver));
+        Self { commands, known }
+    }
+
+    /// The content this writer last wrote, shared with the settings watcher.
+    pub fn known_content(&self) -> KnownContent {
+        Arc::clone(&self.known)
+    }
+
+    /// Queues `settings` for writing to `dir`. Only the reporter of the last
+    /// save in a burst is called. Sensitive fields are encrypted on the
+    /// writer task, opening the key can wait for a keyring prompt.
+    pub fn save(&self, dir: PathBuf, settings: &AppSettings, report: SaveReporter) {
+        let settings = Box::new(settings.clone());
+        let _ = self.commands.send(Command::Save {
+            dir,
+            settings,
+            report,
+        });
+    }
+
+    /// Writes pending changes now and waits for them to hit the disk.
+    pub fn flush(&self) {
+        let (done, wait) = std_mpsc::channel();
+        if self.commands.send(Command::Flush(done)).is_ok() {
+            let _ = wait.recv_timeout(FLUSH_TIMEOUT);
+        }
+    }
+}
+
+impl Drop for SettingsWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+async fn run(known: KnownContent, mut receiver: mpsc::UnboundedReceiver<Command>) {
+    let mut pending: Option<Pending> = None;
+    let mut written = Written::new();
+    // When the oldest unwritten change arrived.
+    let mut since = Instant::now();
+
+    loop {
+        let command = if pending.is_some() {
+            let deadline = (Instant::now() + DEBOUNCE).min(since + MAX_DELAY);
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(command) => command,
+                Err(_) => {
+                    write_pending(&known, &mut written, &mut pending);
+                    continue;
+                }
+            }
+        } else {
+            receiver.recv().await
+        };
+
+        match command {
+            Some(Command::Save {
+                dir,
+                settings,
+                report,
+            }) => {
+                // A save for another profile doesn't replace this one.
+                if pending.as_ref().is_some_and(|p| p.dir != dir) {
+                    write_pending(&known, &mut written, &mut pending);
+                }
+                if pending.is_none() {
+                    since = Instant::now();
+                }
+                pending = Some(Pending {
+                    dir,
+                    settings,
+                    report,
+                });
+            }
+            Some(Command::Flush(done)) => {
+                write_pending(&known, &mut written, &mut pending);
+                let _ = done.send(());
+            }
+            None => {
+                write_pending(&known, &mut written, &mut pending);
+                break;
+            }
+        }
+    }
+}
+
+fn write_pending(known: &KnownContent, written: &mut Written, pending: &mut Option<Pending>) {
+    let Some(Pending {
+        dir,
+        mut settings,
+        report,
+    }) = pending.take()
+    else {
+        return;
+    };
+    if let Some(version) = settings::newer_schema(&dir) {
+        let message = format!(
+            "Not saving, {} was written by a newer version (schema {})",
+            dir.join(SETTINGS_FILE).display(),
+            version
+        );
+        println!("{}", message);
+        report(Err(message));
+        return;
+    }
+    settings.download_path = paths::relative_to_home(&settings.download_path);
+    let path = dir.join(SETTINGS_FILE);
+    let json = match serialize(&mut settings, written.get(&path)) {
+        Ok(json) => json,
+        Err(e) => {
+            report(Err(format!("Failed to serialize settings: {}", e)));
+            return;
+        }
+    };
+    // Recorded before the rename so the watcher never sees it as foreign.
+    known.lock().unwrap().insert(path.clone(), json.1.clone());
+    let result = write_atomic(&dir, &json.1).map_err(|e| e.to_string());
+    match &result {
+        Ok(()) => {
+            written.insert(path, json);
+        }
+        Err(e) => println!("Failed to save settings: {}", e),
+    }
+    report(result);
+}
+
+/// The settings as JSON, before and after encrypting their sensitive fields.
+/// Settings equal to `last` keep its encrypted JSON.
+fn serialize(
+    settings: &mut AppSettings,
+    last: Option<&(String, String)>,
+) -> serde_json::Result<(String, String)> {
+    let plain = serde_json::to_string_pretty(settings)?;
+    if let Some((_, last_json)) = last.filter(|(last_plain, _)| *last_plain == plain) {
+        return Ok((plain, last_json.clone()));
+    }
+    settings.encrypt_fields();
+    let json = serde_json::to_string_pretty(settings)?;
+    Ok((plain, json))
+}
+
+fn write_atomic(dir: &Path, json: &str) -> std::io::Result<()> {
+    let path = dir.join(SETTINGS_FILE);
+    if fs::read_to_string(&path).is_ok_and(|current| current == json) {
+        return Ok(());
+    }
+
+    let temp = dir.join(format!("{}.tmp", SETTINGS_FILE));
+    {
+        let mut file = File::create(&temp)?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()?;
+    }
+    if path.exists() {
+        rotate_backups(dir, &path);
+    }
+    fs::rename(&temp, &path)?;
+
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Shifts `.bak.1..N` up by one and copies the current file to `.bak.1`.
+fn rotate_backups(dir: &Path, path: &Path) {
+    let backup = |n: usize| dir.join(format!("{}.bak.{}", SETTINGS_FILE, n));
+    let _ = fs::remove_file(backup(BACKUP_COUNT));
+    for n in (1..BACKUP_COUNT).rev() {
+        let _ = fs::rename(backup(n), backup(n + 1));
+    }
+    if let Err(e) = fs::copy(path, backup(1)) {
+        println!("Failed to back up settings: {}", e);
+    }
+}
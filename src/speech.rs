@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAX_SPOKEN_CHARS: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeechMode {
+    /// "Message from Alice".
+    SenderOnly,
+    /// "Alice says: ..." including the message text.
+    Full,
+}
+
+/// Spoken announcements of incoming messages through speech-dispatcher.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeechSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_mode")]
+    pub mode: SpeechMode,
+    /// Announce every chat, otherwise only chats with a `speak` rule.
+    #[serde(default)]
+    pub announce_all: bool,
+    /// Maximum announcements per minute across all accounts, 0 = unlimited.
+    #[serde(default = "default_rate_limit")]
+    pub rate_limit_per_minute: u32,
+    /// speech-dispatcher rate, -100 to 100.
+    #[serde(default)]
+    pub rate: i32,
+    /// Language code passed to speech-dispatcher, empty = its default.
+    #[serde(default)]
+    pub language: String,
+}
+
+fn default_mode() -> SpeechMode {
+    SpeechMode::Full
+}
+
+fn default_rate_limit() -> u32 {
+    6
+}
+
+impl Default for SpeechSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: default_mode(),
+            announce_all: false,
+            rate_limit_per_minute: default_rate_limit(),
+            rate: 0,
+            language: String::new(),
+        }
+    }
+}
+
+/// Speaks announcements with `spd-say`, dropping those over the rate limit.
+#[derive(Default)]
+pub struct Announcer {
+    recent: VecDeque<Instant>,
+}
+
+impl Announcer {
+    /// `sender` is who wrote the message, `chat` the group it was sent to
+    /// (equal to `sender` for direct chats). An empty `text` is never read.
+    pub fn announce(&mut self, settings: &SpeechSettings, sender: &str, chat: &str, text: &str) {
+        if self.rate_limited(settings.rate_limit_per_minute) {
+            return;
+        }
+
+        let from = if sender == chat {
+            sender.to_string()
+        } else {
+            format!("{} in {}", sender, chat)
+        };
+        let utterance = match settings.mode {
+            SpeechMode::Full if !text.trim().is_empty() => format!(
+                "{} says: {}",
+                from,
+                text.chars().take(MAX_SPOKEN_CHARS).collect::<String>()
+            ),
+            _ => format!("Message from {}", from),
+        };
+        speak(settings, utterance);
+    }
+
+    fn rate_limited(&mut self, limit: u32) -> bool {
+        if limit == 0 {
+            return false;
+        }
+        let now = Instant::now();
+        while self
+            .recent
+            .front()
+            .is_some_and(|spoken| now.duration_since(*spoken) > Duration::from_secs(60))
+        {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= limit as usize {
+            return true;
+        }
+        self.recent.push_back(now);
+        false
+    }
+}
+
+fn speak(settings: &SpeechSettings, utterance: String) {
+    let mut command = std::process::Command::new("spd-say");
+    command
+        .args(["--application-name", "WhatsApp-QT"])
+        .args(["--rate", &settings.rate.clamp(-100, 100).to_string()]);
+    if !settings.language.is_empty() {
+        command.args(["--language", &settings.language]);
+    }
+    command.arg("--").arg(utterance);
+
+    thread::spawn(move || {
+        if let Err(e) = command.status() {
+            println!("spd-say not available, announcement skipped: {}", e);
+        }
+    });
+}
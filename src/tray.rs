@@ -0,0 +1,32 @@
+use std::sync::mpsc;
+use std::sync::{Mutex, OnceLock};
+
+/// Icon theme name shown in the tray while there are unseen missed calls.
+pub const MISSED_CALL_ICON: &str = "call-missed";
+
+static UPDATES: OnceLock<Mutex<mpsc::Sender<u32>>> = OnceLock::new();
+
+/// Missed-call counts for the tray thread. Only the first caller gets
+/// updates.
+pub fn missed_call_updates() -> mpsc::Receiver<u32> {
+    let (sender, receiver) = mpsc::channel();
+    if UPDATES.set(Mutex::new(sender)).is_err() {
+        println!("Tray updates are already being received");
+    }
+    receiver
+}
+
+/// Title line of the tray menu, with the missed calls when there are any.
+pub fn label(missed: u32) -> String {
+    match missed {
+        0 => "WhatsApp Desktop".to_string(),
+        1 => "WhatsApp Desktop - 1 missed call".to_string(),
+        n => format!("WhatsApp Desktop - {} missed calls", n),
+    }
+}
+
+pub fn set_missed_calls(count: u32) {
+    if let Some(updates) = UPDATES.get() {
+        let _ = updates.lock().unwrap().send(count);
+    }
+}
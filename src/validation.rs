@@ -0,0 +1,206 @@
+use chrono::NaiveTime;
+use reqwest::Url;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::css;
+use crate::profiles;
+use crate::push::PushService;
+use crate::settings::{self, AppSettings};
+
+/// Probe file created to check that a directory is writable.
+const WRITE_CHECK_FILE: &str = ".whatsapp-qt-write-check";
+
+/// A setting that can't be used, `field` is its dotted path in
+/// `settings.json`, e.g. `notification_settings.digest.times[1]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// The theme has to exist and render, a user theme can have typos.
+pub fn check_theme(theme: &str) -> Result<(), String> {
+    if theme == css::SYSTEM_THEME {
+        return Ok(());
+    }
+    css::load(theme).map(|_| ())
+}
+
+/// The download folder has to be an existing, writable directory.
+pub fn check_download_path(path: &str) -> Result<(), String> {
+    if path.trim().is_empty() {
+        return Err("Choose a download folder".to_string());
+    }
+    let dir = Path::new(path);
+    match fs::metadata(dir) {
+        Ok(meta) if meta.is_dir() => {}
+        Ok(_) => return Err(format!("{} is not a folder", path)),
+        Err(_) => return Err(format!("Folder {} does not exist", path)),
+    }
+    let probe = dir.join(WRITE_CHECK_FILE);
+    match fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Ok(())
+        }
+        Err(e) => Err(format!("Folder {} is not writable: {}", path, e)),
+    }
+}
+
+fn check_url(url: &str) -> Result<(), String> {
+    match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+        Ok(parsed) => Err(format!(
+            "Unsupported URL scheme \"{}\", use http or https",
+            parsed.scheme()
+        )),
+        Err(e) => Err(format!("Invalid URL \"{}\": {}", url, e)),
+    }
+}
+
+/// Every problem in `settings`, empty when they can be used as they are.
+pub fn validate(settings: &AppSettings) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let mut check = |field: String, result: Result<(), String>| {
+        if let Err(message) = result {
+            errors.push(FieldError::new(field, message));
+        }
+    };
+
+    check("theme".into(), check_theme(&settings.theme));
+    for service in settings.theme_strategies.keys() {
+        if !settings::SERVICES.contains(&service.as_str()) {
+            check(
+                format!("theme_strategies.{}", service),
+                Err(format!(
+                    "Unknown service, expected one of {}",
+                    settings::SERVICES.join(", ")
+                )),
+            );
+        }
+    }
+    check(
+        "download_path".into(),
+        check_download_path(&settings.download_path),
+    );
+    let tab_count = settings.tabs.len().max(1) as i32;
+    check(
+        "current_tab".into(),
+        if (0..tab_count).contains(&settings.current_tab) {
+            Ok(())
+        } else {
+            Err(format!("No tab number {}", settings.current_tab))
+        },
+    );
+
+    let mut storages = HashSet::new();
+    for (i, tab) in settings.tabs.iter().enumerate() {
+        if tab.name.trim().is_empty() {
+            check(format!("tabs[{}].name", i), Err("Name the tab".into()));
+        }
+        let storage = if !profiles::is_valid_name(&tab.storage) {
+            Err(format!("Invalid web profile name \"{}\"", tab.storage))
+        } else if !storages.insert(tab.storage.as_str()) {
+            Err(format!("Web profile {} is used by two tabs", tab.storage))
+        } else {
+            Ok(())
+        };
+        check(format!("tabs[{}].storage", i), storage);
+    }
+
+    let backup = &settings.backup;
+    if backup.enabled {
+        if backup.interval_hours == 0 {
+            check(
+                "backup.interval_hours".into(),
+                Err("Back up at most once an hour".into()),
+            );
+        }
+        if backup.keep_daily == 0 && backup.keep_weekly == 0 {
+            check(
+                "backup.keep_daily".into(),
+                Err("Keep at least one daily or weekly backup".into()),
+            );
+        }
+    }
+
+    let notifications = &settings.notification_settings;
+    let digest = &notifications.digest;
+    for (i, time) in digest.times.iter().enumerate() {
+        check(
+            format!("notification_settings.digest.times[{}]", i),
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map(|_| ())
+                .map_err(|_| format!("\"{}\" is not a time, use HH:MM", time)),
+        );
+    }
+    if digest.enabled && digest.interval_minutes == 0 && digest.times.is_empty() {
+        check(
+            "notification_settings.digest.interval_minutes".into(),
+            Err("Set an interval or delivery times for the digest".into()),
+        );
+    }
+
+    let speech = &notifications.speech;
+    if !(-100..=100).contains(&speech.rate) {
+        check(
+            "notification_settings.speech.rate".into(),
+            Err(format!("Rate {} is outside -100 to 100", speech.rate)),
+        );
+    }
+
+    let hook = &notifications.command_hook;
+    if hook.enabled {
+        if hook.command.trim().is_empty() {
+            check(
+                "notification_settings.command_hook.command".into(),
+                Err("Enter the command to run".into()),
+            );
+        }
+        if hook.timeout_secs == 0 {
+            check(
+                "notification_settings.command_hook.timeout_secs".into(),
+                Err("Timeout must be at least one second".into()),
+            );
+        }
+    }
+
+    for (i, target) in notifications.push_targets.iter().enumerate() {
+        let field = |name: &str| format!("notification_settings.push_targets[{}].{}", i, name);
+        match &target.service {
+            PushService::Ntfy { server, topic, .. } => {
+                check(field("server"), check_url(server));
+                if topic.trim().is_empty() {
+                    check(field("topic"), Err("Enter the ntfy topic".into()));
+                }
+            }
+            PushService::Gotify { server, token } => {
+                check(field("server"), check_url(server));
+                if token.trim().is_empty() {
+                    check(field("token"), Err("Enter the Gotify app token".into()));
+                }
+            }
+            PushService::Webhook { url, .. } => check(field("url"), check_url(url)),
+        }
+    }
+
+    errors
+}